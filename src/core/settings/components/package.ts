@@ -151,6 +151,8 @@ export class PackagesList {
   }
 
   async removePackage(spec: PackageSpec) {
+    // ? キャッシュだけ消しても VFS に残ったファイルが使われ続けるため
+    await this.plugin.typst.removePackage(`@${spec.namespace}/${spec.name}/${spec.version}`);
     await this.plugin.app.vault.adapter.remove(
       `${this.plugin.cachesDirNPath}/${spec.namespace}_${spec.name}_${spec.version}.cache`,
     );
@@ -1,5 +1,6 @@
 import { type App, debounce, Notice, Platform, PluginSettingTab, Setting } from 'obsidian';
 
+import DEFAULT_RENDERING_SETTINGS from '@/data/defaultSettings.json';
 import type { CodeblockProcessor, DisplayProcessor, ExcalidrawProcessor, InlineProcessor } from '@/libs/processor';
 import type { Snippet } from '@/libs/snippet';
 import type ObsidianTypstMate from '@/main';
@@ -45,113 +46,13 @@ export interface Settings {
 export const DEFAULT_SETTINGS: Settings = {
   enableBackgroundRendering: true,
   autoBaseColor: true,
-  failOnWarning: false,
   baseColor: '#000000',
   enableMathjaxFallback: false,
   skipPreparationWaiting: false,
   enableInlinePreview: true,
-  disablePackageCache: false,
   enableShortcutKeys: true,
   openTypstToolsOnStartup: true,
-  preamble: [
-    '#set page(margin: 0pt, width: auto, height: auto)',
-    '#show raw: set text(size: 1.25em)',
-    '#set text(size: fontsize)',
-  ].join('\n'),
-  processor: {
-    inline: {
-      processors: [
-        {
-          id: 'ce',
-          renderingEngine: 'typst-svg',
-          format: [
-            '#import "@preview/typsium:0.3.0": ce',
-            '#show math.equation: set text(font: ("New Computer Modern Math", "Noto Serif CJK SC"))',
-            '#ce("{CODE}")',
-          ].join('\n'),
-          styling: 'inline-middle',
-          noPreamble: false,
-          fitToParentWidth: false,
-        },
-        {
-          id: 'mid',
-          renderingEngine: 'typst-svg',
-          format: '$\n{CODE}\n$',
-          styling: 'inline-middle',
-          noPreamble: true,
-          fitToParentWidth: false,
-        },
-        {
-          id: 'tex',
-          renderingEngine: 'mathjax',
-          format: '',
-          styling: 'inline',
-          noPreamble: false,
-          fitToParentWidth: false,
-        },
-        {
-          id: '',
-          renderingEngine: 'typst-svg',
-          format: '${CODE}$',
-          styling: 'inline',
-          noPreamble: false,
-          fitToParentWidth: false,
-        },
-      ],
-    },
-    display: {
-      processors: [
-        {
-          id: 'block',
-          renderingEngine: 'typst-svg',
-          format: '$\n{CODE}\n$',
-          styling: 'block',
-          noPreamble: false,
-          fitToParentWidth: false,
-        },
-        {
-          id: '',
-          renderingEngine: 'typst-svg',
-          format: '$\n{CODE}\n$',
-          styling: 'block-center',
-          noPreamble: false,
-          fitToParentWidth: false,
-        },
-      ],
-    },
-    codeblock: {
-      processors: [
-        {
-          id: 'typ',
-          renderingEngine: 'typst-svg',
-          format: '{CODE}',
-          styling: 'block',
-          noPreamble: false,
-          fitToParentWidth: false,
-        },
-        {
-          id: 'typst',
-          renderingEngine: 'typst-svg',
-          format: '```typst\n{CODE}\n```',
-          styling: 'codeblock',
-          noPreamble: true,
-          fitToParentWidth: true,
-        },
-      ],
-    },
-    excalidraw: {
-      processors: [
-        {
-          id: 'default',
-          renderingEngine: 'typst-svg',
-          format: '#set page(margin: 0.25em)\n{CODE}$',
-          styling: 'default',
-          noPreamble: false,
-          fitToParentWidth: false,
-        },
-      ],
-    },
-  },
+  ...DEFAULT_RENDERING_SETTINGS,
   snippets: [
     {
       category: 'Matrix',
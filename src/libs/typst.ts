@@ -1,4 +1,4 @@
-import { Notice } from 'obsidian';
+import { type MarkdownSectionInformation, Notice, TFile } from 'obsidian';
 
 import { DEFAULT_FONT_SIZE } from '@/constants';
 import InlinePreviewElement from '@/core/editor/elements/InlinePreview';
@@ -11,7 +11,7 @@ import { overwriteCustomElements } from '@/utils/custromElementRegistry';
 import { unzip, zip } from '@/utils/packageCompressor';
 
 import type { Processor, ProcessorKind } from './processor';
-import type { PackageSpec } from './worker';
+import type { Inputs, PackageSpec } from './worker';
 
 import './typst.css';
 
@@ -23,6 +23,9 @@ export default class TypstManager {
   beforeId?: string;
   beforeElement: HTMLElement = document.createElement('span');
 
+  // 準備中に描画を待っているコードブロックの，ノート中の位置
+  private waitingSectionInfos = new WeakMap<Element, () => MarkdownSectionInformation | null>();
+
   constructor(plugin: ObsidianTypstMate) {
     this.plugin = plugin;
   }
@@ -49,21 +52,7 @@ export default class TypstManager {
       )
     ).filter((font) => font !== undefined);
 
-    const kind = ['inline', 'display', 'codeblock'];
-    if (this.plugin.excalidrawPluginInstalled) kind.push('excalidraw');
-
-    const processors = kind.flatMap(
-      (kind) =>
-        this.plugin.settings.processor[kind as 'inline' | 'display' | 'codeblock' | 'excalidraw']?.processors.map(
-          (p) => ({
-            kind,
-            id: p.id,
-            format: this.format(p, ''),
-            styling: p.styling,
-            renderingEngine: p.renderingEngine,
-          }),
-        ) ?? [],
-    );
+    const processors = this.processors();
 
     // キャッシュ
     const sources: Map<string, Uint8Array> = new Map();
@@ -88,6 +77,7 @@ export default class TypstManager {
         fonts,
         processors,
         sources,
+        preamble: this.plugin.settings.preamble,
       });
       if (result instanceof Promise) {
         result.then(() => {
@@ -98,7 +88,13 @@ export default class TypstManager {
           for (const el of waitingElements) {
             const content = el.textContent!;
             el.empty();
-            this.render(content, el, el.getAttribute('kind')!);
+            this.render(
+              content,
+              el,
+              el.getAttribute('kind')!,
+              el.getAttribute('source-path') ?? undefined,
+              this.waitingSectionInfos.get(el),
+            );
           }
         });
       } else {
@@ -106,13 +102,52 @@ export default class TypstManager {
         this.plugin.updateCrashStatus(false);
       }
     } else {
-      await this.plugin.typst.store({ fonts, processors, sources });
+      await this.plugin.typst.store({ fonts, processors, sources, preamble: this.plugin.settings.preamble });
 
       this.ready = true;
       this.plugin.updateCrashStatus(false);
     }
   }
 
+  private processors() {
+    const kind = ['inline', 'display', 'codeblock'];
+    if (this.plugin.excalidrawPluginInstalled) kind.push('excalidraw');
+
+    return kind.flatMap(
+      (kind) =>
+        this.plugin.settings.processor[kind as 'inline' | 'display' | 'codeblock' | 'excalidraw']?.processors.map(
+          (p) => ({
+            kind,
+            id: p.id,
+            format: p.format,
+            noPreamble: p.noPreamble ?? false,
+            styling: p.styling,
+            renderingEngine: p.renderingEngine,
+          }),
+        ) ?? [],
+    );
+  }
+
+  // 描画時の sys.inputs。数式のあるノート (sourcePath) のタイトル・フロントマターと，テーマ
+  // ? sourcePath が分からないときはアクティブなノートを使う
+  inputs(sourcePath?: string): Inputs {
+    const source = sourcePath ? this.plugin.app.vault.getAbstractFileByPath(sourcePath) : null;
+    const file = source instanceof TFile ? source : this.plugin.app.workspace.getActiveFile();
+    const frontmatter = file ? this.plugin.app.metadataCache.getFileCache(file)?.frontmatter : undefined;
+
+    return {
+      title: file?.basename ?? '',
+      theme: document.body.hasClass('theme-dark') ? 'dark' : 'light',
+      frontmatter: frontmatter ?? {},
+    };
+  }
+
+  // 設定の変更 (プロセッサー・前文) を反映する
+  async updateProcessors() {
+    if (!this.ready) return;
+    await this.plugin.typst.setProcessors(this.processors(), this.plugin.settings.preamble);
+  }
+
   registerOnce() {
     overwriteCustomElements('typstmate-svg', TypstSVGElement);
     overwriteCustomElements('typstmate-symbols', SymbolSuggestElement);
@@ -122,16 +157,19 @@ export default class TypstManager {
     // コードブロックプロセッサーをオーバライド
     for (const processor of this.plugin.settings.processor.codeblock?.processors ?? []) {
       try {
-        this.plugin.registerMarkdownCodeBlockProcessor(processor.id, (source, el, _ctx) => {
+        this.plugin.registerMarkdownCodeBlockProcessor(processor.id, (source, el, ctx) => {
+          const sectionInfo = () => ctx.getSectionInfo(el);
           if (!this.ready) {
             el.textContent = source;
             el.addClass('typstmate-waiting');
             el.setAttribute('kind', processor.id);
+            el.setAttribute('source-path', ctx.sourcePath);
+            this.waitingSectionInfos.set(el, sectionInfo);
 
             return Promise.resolve(el as HTMLElement);
           }
 
-          return Promise.resolve(this.render(source, el, processor.id));
+          return Promise.resolve(this.render(source, el, processor.id, ctx.sourcePath, sectionInfo));
         });
       } catch {
         new Notice(`Already registered codeblock language: ${processor.id}`);
@@ -139,6 +177,7 @@ export default class TypstManager {
     }
 
     // MathJax をオーバライド
+    // ? tex2chtml には数式のあるノートが渡されないので，sys.inputs はアクティブなノートのものになる
     window.MathJax!.tex2chtml = (e: string, r: { display?: boolean }) => {
       // タグ名，クラス名，属性がこれ以外だと認識されないない
       const container = document.createElement('mjx-container');
@@ -157,7 +196,14 @@ export default class TypstManager {
     };
   }
 
-  render(code: string, containerEl: Element, kind: string): HTMLElement {
+  // sourcePath は数式のあるノート (sys.inputs に使う)，sectionInfo はその中の位置 (修正の適用に使う)
+  render(
+    code: string,
+    containerEl: Element,
+    kind: string,
+    sourcePath?: string,
+    sectionInfo?: () => MarkdownSectionInformation | null,
+  ): HTMLElement {
     // プロセッサーを決定
     let processor: Processor;
     switch (kind) {
@@ -219,6 +265,8 @@ export default class TypstManager {
     typstSVGEl.kind = kind as ProcessorKind;
     typstSVGEl.source = code;
     typstSVGEl.processor = processor;
+    typstSVGEl.sourcePath = sourcePath;
+    typstSVGEl.sectionInfo = sectionInfo;
     containerEl.appendChild(typstSVGEl);
     // ちらつき防止
     if (this.beforeKind === kind && this.beforeId === processor.id) typstSVGEl.replaceChildren(this.beforeElement);
@@ -229,12 +277,6 @@ export default class TypstManager {
     return containerEl as HTMLElement;
   }
 
-  private format(processer: Processor, code: string) {
-    return processer.noPreamble
-      ? processer.format.replace('{CODE}', code)
-      : `${this.plugin.settings.preamble}\n${processer.format.replace('{CODE}', code)}`;
-  }
-
   private async collectFiles(
     baseDirPath: string,
     dirPath: string,
@@ -3,14 +3,15 @@ import type pathModule from 'node:path';
 
 import { expose } from 'comlink';
 import type { EditorPosition } from 'obsidian';
-import pako from 'pako';
-import untar from 'untar-sync';
 
-import init, { type InitOutput, Typst } from '../../pkg/typst_wasm.js';
+import type { Settings } from '@/core/settings/settings';
+
+import init, { type InitOutput, Typst, unpack_package_archive } from '../../pkg/typst_wasm.js';
 
 let main: Main;
 
 const map = new Map<string, Uint8Array | undefined>();
+const loading = new Map<string, Promise<void>>();
 const xhr = new XMLHttpRequest();
 xhr.overrideMimeType('text/plain; charset=x-user-defined');
 
@@ -39,22 +40,128 @@ export default class $ {
     this.typst = new Typst(this.fetch.bind(this), fontsize);
   }
 
-  store(args: Args): void {
-    this.typst.store(args.fonts ?? [], args.sources ?? [], args.processors ?? []);
+  store(args: Args): StoreError[] {
+    return this.typst.store(args.fonts ?? [], args.sources ?? [], args.processors ?? [], args.preamble);
+  }
+
+  setProcessors(processors: Processor[], preamble: string): void {
+    this.typst.set_processors(processors, preamble);
+  }
+
+  svg(
+    code: string,
+    kind: string,
+    id: string,
+    inputs?: Inputs,
+    waited = new Set<string>(),
+  ): SVGResult | Promise<SVGResult> {
+    const result: SVGResult | NeedsFiles = this.typst.svg(code, kind, id, inputs);
+    if (!('needs' in result)) return result;
+
+    return this.waitFor(result.needs, waited).then(() => this.svg(code, kind, id, inputs, waited));
+  }
+
+  // store で登録したプロセッサーで包んで描画する (width は fitToParentWidth の幅 pt)
+  svgSnippet(
+    code: string,
+    kind: string,
+    id: string,
+    width?: number,
+    inputs?: Inputs,
+    waited = new Set<string>(),
+  ): SVGResult | Promise<SVGResult> {
+    const result: SVGResult | NeedsFiles = this.typst.svg_snippet(code, kind, id, width, inputs);
+    if (!('needs' in result)) return result;
+
+    return this.waitFor(result.needs, waited).then(() => this.svgSnippet(code, kind, id, width, inputs, waited));
+  }
+
+  // svgSnippet の診断の書き出し (診断と同じ順)。モーダルを開いたときに求める
+  reportSnippet(
+    code: string,
+    kind: string,
+    id: string,
+    inputs?: Inputs,
+    waited = new Set<string>(),
+  ): Report[] | Promise<Report[]> {
+    const result: Report[] | NeedsFiles = this.typst.report_snippet(code, kind, id, inputs);
+    if (!('needs' in result)) return result;
+
+    return this.waitFor(result.needs, waited).then(() => this.reportSnippet(code, kind, id, inputs, waited));
+  }
+
+  pdf(
+    filename: string,
+    code: string,
+    options?: PDFOptions,
+    waited = new Set<string>(),
+  ): PDFResult | Promise<PDFResult> {
+    const result: PDFResult | NeedsFiles = this.typst.pdf(filename, code, options);
+    if (!('needs' in result)) return result;
+
+    return this.waitFor(result.needs, waited).then(() => this.pdf(filename, code, options, waited));
   }
 
-  svg(code: string, kind: string, id: string): SVGResult {
-    return this.typst.svg(code, kind, id);
+  lintNotes(notes: Note[], settings: Settings, waited = new Set<string>()): NoteReport[] | Promise<NoteReport[]> {
+    const result: NoteReport[] | NeedsFiles = this.typst.lint_notes(notes, settings);
+    if (!('needs' in result)) return result;
+
+    return this.waitFor(result.needs, waited).then(() => this.lintNotes(notes, settings, waited));
   }
 
-  pdf(filename: string, code: string): PDFResult {
-    return this.typst.pdf(filename, code);
+  // 非同期に読み込み中のファイルを待ってから再コンパイルする
+  // ? コンパイルごとに 1 つずつしか分からないこともあるので，新しいファイルを要求される限り続ける
+  private async waitFor(paths: string[], waited: Set<string>): Promise<void> {
+    if (paths.every((p) => waited.has(p))) {
+      const message = `failed to load files: ${paths.join(', ')}`;
+      throw [
+        {
+          severity: 1,
+          span: { start: 0, end: 0 },
+          message,
+          trace: [],
+          hints: [],
+          fixes: [],
+        },
+      ] satisfies Diagnostic[];
+    }
+
+    for (const p of paths) waited.add(p);
+    await Promise.all(paths.map((p) => loading.get(p)));
   }
 
   findBracketPairs(code: string): BracketPair[] {
     return this.typst.find_bracket_pairs(code);
   }
 
+  enclosingNodes(code: string, mode: SyntaxMode, cursor: number): SyntaxNode[] {
+    return this.typst.enclosing_nodes(code, mode, cursor);
+  }
+
+  expandSelection(code: string, mode: SyntaxMode, from: number, to: number): Selection {
+    return this.typst.expand_selection(code, mode, from, to);
+  }
+
+  shrinkSelection(code: string, mode: SyntaxMode, from: number, to: number): Selection {
+    return this.typst.shrink_selection(code, mode, from, to);
+  }
+
+  editTable(code: string, mode: SyntaxMode, cursor: number, op: TableOp): TableEdit {
+    return this.typst.edit_table(code, mode, cursor, op);
+  }
+
+  syntaxContext(code: string, mode: SyntaxMode, cursor: number): SyntaxContext {
+    return this.typst.syntax_context(code, mode, cursor);
+  }
+
+  canFireSnippet(code: string, mode: SyntaxMode, cursor: number, filter: string): boolean {
+    return this.typst.can_fire_snippet(code, mode, cursor, filter);
+  }
+
+  expandSnippet(body: string, trigger: string, regex: boolean, before: string): SnippetExpansion | null {
+    return this.typst.expand_snippet(body, trigger, regex, before);
+  }
+
   listFonts(): FontInfo[] {
     return this.typst.list_fonts();
   }
@@ -63,6 +170,75 @@ export default class $ {
     return this.typst.get_font_info(font);
   }
 
+  installPackageArchive(spec: string, targz: Uint8Array): string[] {
+    return this.typst.install_package_archive(spec, targz);
+  }
+
+  exportPackageBundle(specs?: string[]): Uint8Array {
+    return this.typst.export_package_bundle(specs ?? null);
+  }
+
+  importPackageBundle(bundle: Uint8Array): BundleImport {
+    return this.typst.import_package_bundle(bundle);
+  }
+
+  packageDependencies(spec: string): PackageDependency[] {
+    return this.typst.package_dependencies(spec);
+  }
+
+  packageDependents(spec: string): PackageSpec[] {
+    return this.typst.package_dependents(spec);
+  }
+
+  packageManifest(spec: string): PackageManifest | null {
+    return this.typst.package_manifest(spec);
+  }
+
+  supplyFile(path: string, bytes: Uint8Array): void {
+    map.set(path, bytes);
+    this.typst.supply_file(path, bytes);
+  }
+
+  removeFile(path: string): boolean {
+    map.delete(path);
+    return this.typst.remove_file(path);
+  }
+
+  removePackage(spec: string): boolean {
+    for (const key of map.keys()) if (key.startsWith(`${spec}/`)) map.delete(key);
+    return this.typst.remove_package(spec);
+  }
+
+  invalidate(path: string): boolean {
+    map.delete(path);
+    return this.typst.invalidate(path);
+  }
+
+  setNegativeCache(enabled: boolean): void {
+    this.typst.set_negative_cache(enabled);
+  }
+
+  failedFiles(): FailedFile[] {
+    return this.typst.failed_files();
+  }
+
+  clearFailures(): number {
+    for (const [k, v] of map) if (v === undefined) map.delete(k);
+    return this.typst.clear_failures();
+  }
+
+  latestPackages(): PackageSpec[] {
+    return this.typst.latest_packages();
+  }
+
+  checkPackageImports(code: string): PackageImport[] {
+    return this.typst.check_package_imports(code);
+  }
+
+  upgradePackageImports(code: string): string {
+    return this.typst.upgrade_package_imports(code);
+  }
+
   listPackages(): PackageSpec[] {
     return this.typst.list_packages();
   }
@@ -75,7 +251,7 @@ export default class $ {
     if (map.has(path)) {
       const v = map.get(path);
       if (v) return v;
-      throw 12; // FileError::NotFound
+      throw { kind: 'not_found', path } satisfies FetchError;
     }
 
     let isPackage = false;
@@ -86,19 +262,24 @@ export default class $ {
 
       const result = f(rpath);
       if (result instanceof Promise) {
-        result
-          .then((r) => {
-            map.set(vpath, new Uint8Array(r));
-          })
-          .catch(() => {
-            if (!map.has(vpath)) map.set(vpath, undefined);
-          });
+        if (!loading.has(vpath))
+          loading.set(
+            vpath,
+            result
+              .then((r) => {
+                map.set(vpath, new Uint8Array(r));
+              })
+              .catch(() => {
+                if (!map.has(vpath)) map.set(vpath, undefined);
+              })
+              .finally(() => loading.delete(vpath)),
+          );
       } else {
         map.set(vpath, result);
         return new Uint8Array(result);
       }
 
-      throw 0; // FileError::Other(implementation constraints)
+      throw { kind: 'pending' } satisfies FetchError; // コンパイル後に needs として返る
     };
 
     if (path.startsWith('@')) {
@@ -119,9 +300,11 @@ export default class $ {
 
         xhr.open('GET', `https://packages.typst.org/preview/${name}-${version}.tar.gz`, false);
         xhr.send(null);
-        if (xhr.status === 0) throw 21; // PackageError::NetworkFailed
-        if (xhr.status === 404) throw 22; // PackageError::NotFound
-        if (!xhr.responseText === null) throw 20; // PackageError::MalformedArchive
+        if (xhr.status === 0)
+          throw { kind: 'network_failed', message: 'network connection error' } satisfies FetchError;
+        if (xhr.status === 404) throw { kind: 'package_not_found' } satisfies FetchError;
+        if (!xhr.responseText === null)
+          throw { kind: 'malformed_archive', message: 'empty response' } satisfies FetchError;
 
         try {
           const text = xhr.responseText;
@@ -130,27 +313,15 @@ export default class $ {
             targzArr[i] = text.charCodeAt(i) & 0xff;
           }
 
-          const tarArr = pako.ungzip(targzArr).buffer;
-          const files = untar(tarArr) as {
-            name: string;
-            buffer: ArrayBuffer;
-            type: string;
-            linkname?: string;
-          }[];
+          const files = unpack_package_archive(`@${p}`, targzArr) as Map<string, Uint8Array>;
           main.writePackage(p, files);
 
-          for (const f of files.filter((f) => f.type === '0')) {
-            map.set(`@${p}/${f.name}`, new Uint8Array(f.buffer));
-          }
-
-          for (const f of files.filter((f) => f.type === '2')) {
-            map.set(`@${p}/${f.name}`, new Uint8Array(map.get(f.linkname!)?.buffer || new ArrayBuffer(0)));
-          }
+          for (const [fpath, data] of files) map.set(`@${p}/${fpath}`, data);
 
           if (vpath === 'typst.toml') main.notice(`Downloaded successfully!`, 500);
         } catch (e) {
           console.error(e);
-          throw 20; // PackageError::MalformedArchive;
+          throw { kind: 'malformed_archive', message: String(e) } satisfies FetchError;
         }
 
         return map.get(`@${p}/${vpath}`);
@@ -161,13 +332,15 @@ export default class $ {
       for (const localPackagesDirPath of this.localPackagesDirPaths) {
         try {
           return readBinary(`@${path}`, `${localPackagesDirPath}/${path}`);
-        } catch {}
+        } catch (e) {
+          if ((e as FetchError)?.kind === 'pending') throw e;
+        }
       }
     } else {
       return readBinary(path, path);
     }
 
-    throw 0; // FileError::Other(implementation constraints)
+    throw { kind: 'package_not_found' } satisfies FetchError;
   }
 
   setMain(m: Main): void {
@@ -180,13 +353,16 @@ expose($, self as any);
 interface Processor {
   kind: string;
   id: string;
+  // `{CODE}` を含む format そのもの
   format: string;
+  noPreamble?: boolean;
 }
 
 type Args = {
   fonts?: ArrayBuffer[];
   sources?: Map<string, Uint8Array>;
   processors?: Processor[];
+  preamble?: string;
 };
 
 export interface FontVariant {
@@ -208,21 +384,134 @@ export interface PackageSpec {
   version: string;
 }
 
+export interface PackageManifest {
+  spec: PackageSpec;
+  entrypoint: string;
+  description?: string;
+  authors: string[];
+  license?: string;
+  homepage?: string;
+  repository?: string;
+  keywords: string[];
+  categories: string[];
+  compiler?: string;
+  exclude: string[];
+}
+
+export interface PackageImport {
+  source: string;
+  from: number;
+  to: number;
+  spec?: PackageSpec;
+  installed: boolean;
+  latest?: string;
+}
+
+export interface PackageDependency {
+  spec: PackageSpec;
+  present: boolean;
+  dependencies: PackageSpec[];
+}
+
+export interface BundleImport {
+  packages: PackageSpec[];
+  errors: StoreError[];
+}
+
+export interface FailedFile {
+  path: string;
+  message: string;
+}
+
+export interface StoreError {
+  path: string;
+  message: string;
+}
+
+// 0 始まりの行と，UTF-16 の列・オフセット
+export interface DiagnosticPosition {
+  line: number;
+  column: number;
+  offset: number;
+}
+
+export interface DiagnosticLocation {
+  path: string;
+  // `@namespace/name:version`
+  package?: string;
+  start: DiagnosticPosition;
+  end: DiagnosticPosition;
+}
+
+// UTF-16 の範囲 from..to を text に置き換える
+export interface TextEdit {
+  from: number;
+  to: number;
+  text: string;
+}
+
+export interface Fix {
+  title: string;
+  edits: TextEdit[];
+}
+
 export interface Diagnostic {
   severity: number;
   span: {
     start: number;
     end: number;
   };
+  location?: DiagnosticLocation;
+  // svgSnippet のみ。数式・前文中の UTF-16 の範囲
+  origin?: {
+    kind: 'snippet' | 'preamble' | 'template';
+    start: number;
+    end: number;
+  };
   message: string;
   trace: {
     span: {
       start: number;
       end: number;
     };
+    location?: DiagnosticLocation;
     point: string;
   }[];
   hints: string[];
+  // svgSnippet のみ。数式に対する修正
+  fixes: Fix[];
+}
+
+// Typst CLI と同じ形式 (ソースの抜粋・トレース・ヒント)
+export interface Report {
+  text: string;
+  // typstmate-diag-* のクラスを付けた <pre> の中身
+  html: string;
+}
+
+// fetch が投げるエラー (Rust 側で FileError に変換される)
+export interface FetchError {
+  kind:
+    | 'pending'
+    | 'not_found'
+    | 'access_denied'
+    | 'is_directory'
+    | 'not_source'
+    | 'invalid_utf8'
+    | 'package_not_found'
+    | 'version_not_found'
+    | 'network_failed'
+    | 'malformed_archive'
+    | 'package_other'
+    | 'other';
+  path?: string;
+  message?: string;
+  package?: string;
+  latest?: string;
+}
+
+export interface NeedsFiles {
+  needs: string[];
 }
 
 export interface SVGResult {
@@ -230,9 +519,52 @@ export interface SVGResult {
   diags: Diagnostic[];
 }
 
+// sys.inputs (JSON と同じ値)
+export type Inputs = Record<string, unknown>;
+
+export interface PDFOptions {
+  // 指定した重大度の診断だけを返す
+  severity?: 'error' | 'warning';
+  inputs?: Inputs;
+}
+
+export interface FileDiagnostics {
+  // 位置のない診断なら undefined
+  path?: string;
+  // `@namespace/name:version`
+  package?: string;
+  diags: Diagnostic[];
+}
+
 export interface PDFResult {
   pdf: Uint8Array;
-  diags: Diagnostic[];
+  // メインファイル，他のファイル，位置のないものの順
+  files: FileDiagnostics[];
+}
+
+export interface Note {
+  path: string;
+  markdown: string;
+}
+
+// 位置は EditorPosition と同じく 0 始まりの行と UTF-16 の列
+export interface LintDiagnostic {
+  kind: 'inline' | 'display' | 'codeblock';
+  id: string;
+  line: number;
+  ch: number;
+  from: number;
+  to: number;
+  origin: 'snippet' | 'preamble' | 'template' | 'other';
+  diagnostic: Diagnostic;
+  // ノート中の UTF-16 の範囲に対する修正
+  fixes: Fix[];
+}
+
+export interface NoteReport {
+  path: string;
+  errors: LintDiagnostic[];
+  warnings: LintDiagnostic[];
 }
 
 export interface BracketPair {
@@ -244,8 +576,47 @@ export interface BracketPair {
   close_pos: EditorPosition;
 }
 
+export type SyntaxMode = 'markup' | 'math' | 'code';
+
+export interface SyntaxNode {
+  kind: string;
+  from: number;
+  to: number;
+}
+
+export interface Selection {
+  from: number;
+  to: number;
+}
+
+export type TableOp =
+  | 'insert_row_above'
+  | 'insert_row_below'
+  | 'delete_row'
+  | 'insert_column_left'
+  | 'insert_column_right'
+  | 'delete_column'
+  | 'transpose'
+  | 'align';
+
+export interface TableEdit {
+  text: string;
+  cursor: number;
+}
+
+export type SyntaxContext = 'markup' | 'math' | 'code' | 'string' | 'comment' | 'raw';
+
+export interface SnippetExpansion {
+  text: string;
+  replace_from: number;
+  tabstops: {
+    index: number | null;
+    ranges: Selection[];
+  }[];
+}
+
 export interface Main {
   notice(message: string, duration?: number): void;
   readBinary(path: string): Uint8Array | Promise<ArrayBuffer>;
-  writePackage(path: string, files: tarFile[]): void;
+  writePackage(path: string, files: Map<string, Uint8Array>): void;
 }
@@ -315,6 +315,16 @@ export default class ObsidianTypstMate extends Plugin {
   }
 
   private registerListeners() {
+    // ? Typst 側に読み込み済みのファイルは，変更されたら取得し直させる
+    this.registerEvent(this.app.vault.on('modify', (file) => this.invalidateFile(file.path)));
+    this.registerEvent(this.app.vault.on('delete', (file) => this.invalidateFile(file.path)));
+    this.registerEvent(
+      this.app.vault.on('rename', (file, oldPath) => {
+        this.invalidateFile(oldPath);
+        this.invalidateFile(file.path);
+      }),
+    );
+
     this.listeners.push(
       this.app.workspace.on('css-change', this.applyBaseColor.bind(this)),
       this.app.workspace.on('active-leaf-change', this.editorHelper.onActiveLeafChange.bind(this.editorHelper)),
@@ -385,6 +395,15 @@ export default class ObsidianTypstMate extends Plugin {
     );
   }
 
+  private invalidateFile(path: string) {
+    // ? `@` から始まるパスはパッケージのファイルとして解釈されるため
+    if (path.startsWith('@')) return;
+
+    try {
+      Promise.resolve(this.typst?.invalidate(path)).catch(() => {});
+    } catch {}
+  }
+
   async init() {
     this.worker?.terminate();
 
@@ -405,27 +424,24 @@ export default class ObsidianTypstMate extends Plugin {
         return fs.readFileSync(`${baseDirPath}/${p}`);
       },
 
-      async writePackage(path: string, files: tarFile[]) {
+      async writePackage(path: string, files: Map<string, Uint8Array>) {
         const map = new Map<string, Uint8Array>();
 
         // ディレクトリ
-        for (const file of files.filter((f) => f.type === '5')) {
-          await adapter.mkdir(`${packagesDirNPath}/${path}/${file.name}`);
+        // ? リンクは Rust 側で解決済みなので，ファイルの親ディレクトリだけ作ればよい
+        const dirs = new Set<string>();
+        for (const fpath of files.keys()) {
+          const parts = fpath.split('/').slice(0, -1);
+          for (let i = 1; i <= parts.length; i++) dirs.add(parts.slice(0, i).join('/'));
         }
-
-        // ファイル
-        for (const file of files.filter((f) => f.type === '0')) {
-          await adapter.writeBinary(`${packagesDirNPath}/${path}/${file.name}`, file.buffer);
-          map.set(`${path}/${file.name}`, new Uint8Array(file.buffer));
+        for (const dir of [...dirs].sort()) {
+          await adapter.mkdir(`${packagesDirNPath}/${path}/${dir}`);
         }
 
-        // シンボリックリンク
-        for (const file of files.filter((f) => f.type === '2')) {
-          await adapter.copy(
-            `${packagesDirNPath}/${path}/${file.name}`,
-            `${packagesDirNPath}/${path}/${file.linkname}`,
-          );
-          map.set(`${path}/${file.linkname}`, map.get(`${path}/${file.name}`)!);
+        // ファイル
+        for (const [fpath, data] of files) {
+          await adapter.writeBinary(`${packagesDirNPath}/${path}/${fpath}`, data.slice().buffer);
+          map.set(`${path}/${fpath}`, data);
         }
 
         const [namespace, name, version] = path.split('/');
@@ -496,6 +512,7 @@ export default class ObsidianTypstMate extends Plugin {
 
   async saveSettings() {
     await this.saveData(this.settings);
+    await this.typstManager?.updateProcessors();
   }
 
   override onConfigFileChange = debounce(this.loadSettings.bind(this), 500, true);
@@ -8,3 +8,12 @@ declare module '@/data/shortcuts.json' {
 
   export default data;
 }
+
+// ? CLI (wasm/src/vault/settings.rs) と共有する既定値
+declare module '@/data/defaultSettings.json' {
+  import type { Settings } from '@/core/settings/settings';
+
+  const data: Pick<Settings, 'failOnWarning' | 'disablePackageCache' | 'preamble' | 'processor'>;
+
+  export default data;
+}
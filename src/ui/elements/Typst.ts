@@ -1,33 +1,40 @@
+import { type MarkdownSectionInformation, TFile } from 'obsidian';
+
 import type { Processor, ProcessorKind } from '@/libs/processor';
-import type { Diagnostic, SVGResult } from '@/libs/worker';
+import type { Diagnostic, Fix, SVGResult } from '@/libs/worker';
 import type ObsidianTypstMate from '@/main';
-import { DiagnosticModal } from '@/ui/modals/diagnostic';
+import { applyFix, DiagnosticModal } from '@/ui/modals/diagnostic';
 
 export default class TypstElement extends HTMLElement {
   kind!: ProcessorKind;
   source!: string;
   processor!: Processor;
+  // 数式のあるノート。分からなければアクティブなノートの sys.inputs を使う
+  sourcePath?: string;
+  // ノート中のセクションの位置。分からなければ修正を適用できない
+  sectionInfo?: () => MarkdownSectionInformation | null;
 
   renderingFormat!: 'svg';
 
   plugin!: ObsidianTypstMate;
 
   async render() {
-    const input = this.format();
-
     try {
-      const result = this.plugin.typst.svg(input, this.kind, this.processor.id);
+      const inputs = this.plugin.typstManager.inputs(this.sourcePath);
+      const result = this.plugin.typst.svgSnippet(this.source, this.kind, this.processor.id, undefined, inputs);
 
       if (result instanceof Promise) {
         if (this.kind !== 'inline' && this.processor.fitToParentWidth && !this.source.includes('<br>'))
           this.plugin.observer.register(
             this,
             (entry: ResizeObserverEntry) => {
-              const input =
-                `#let WIDTH = ${(entry.contentRect.width * 3) / 4}pt\n` +
-                this.format().replace('width: auto', 'width: WIDTH');
-
-              const result = this.plugin.typst.svg(input, this.kind, this.processor.id) as Promise<SVGResult>;
+              const result = this.plugin.typst.svgSnippet(
+                this.source,
+                this.kind,
+                this.processor.id,
+                (entry.contentRect.width * 3) / 4,
+                inputs,
+              ) as Promise<SVGResult>;
 
               result
                 .then((result: SVGResult) => this.postProcess(result))
@@ -49,15 +56,6 @@ export default class TypstElement extends HTMLElement {
     return this;
   }
 
-  format() {
-    let formatted = this.processor.format.replace('{CODE}', this.source);
-    formatted = this.processor.noPreamble ? formatted : `${this.plugin.settings.preamble}\n${formatted}`;
-
-    if (this.kind === 'display') formatted = formatted.replaceAll('<br>', '\n');
-
-    return formatted;
-  }
-
   postProcess(result: SVGResult) {
     if (this.plugin.settings.failOnWarning && result.diags.length !== 0) throw result.diags;
 
@@ -79,13 +77,42 @@ export default class TypstElement extends HTMLElement {
 
       diagEl.textContent = `${err[0]?.message}${err[0]?.hints.length !== 0 ? ` [${err[0]?.hints.length} hints]` : ''}`;
 
-      // TODO: エラー箇所を表示する
-      if (err[0]?.hints.length !== 0)
-        diagEl.addEventListener('click', () => new DiagnosticModal(this.plugin.app, err).open());
+      diagEl.addEventListener('click', () =>
+        new DiagnosticModal(
+          this.plugin.app,
+          err,
+          this.sectionInfo ? (fix) => this.applyFix(fix) : undefined,
+          () =>
+            this.plugin.typst.reportSnippet(
+              this.source,
+              this.kind,
+              this.processor.id,
+              this.plugin.typstManager.inputs(this.sourcePath),
+            ),
+        ).open(),
+      );
 
       this.plugin.typstManager.beforeKind = this.kind;
       this.plugin.typstManager.beforeId = this.processor.id;
       this.replaceChildren(diagEl);
     }
   }
+
+  // ノート中の数式を修正後のものに置き換える
+  // ? ノートやその中の位置が分からないとき，描画後に書き換えられていたときは何もしない
+  async applyFix(fix: Fix) {
+    const info = this.sectionInfo?.();
+    const file = this.sourcePath ? this.plugin.app.vault.getAbstractFileByPath(this.sourcePath) : null;
+    if (!info || !(file instanceof TFile)) return;
+
+    // ? セクションはフェンスの行を含むので，その内側を置き換える
+    const content = await this.plugin.app.vault.read(file);
+    const lines = content.split('\n');
+    const start = info.lineStart + 1;
+    const end = info.lineEnd;
+    if (lines.slice(start, end).join('\n') !== this.source) return;
+
+    lines.splice(start, end - start, ...applyFix(this.source, fix).split('\n'));
+    await this.plugin.app.vault.modify(file, lines.join('\n'));
+  }
 }
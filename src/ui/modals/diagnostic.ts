@@ -1,17 +1,78 @@
-import { type App, Modal, Setting } from 'obsidian';
+import { type App, Modal, Notice, Setting, sanitizeHTMLToDom } from 'obsidian';
 
-import type { Diagnostic } from '@/libs/worker';
+import type { Diagnostic, Fix, Report } from '@/libs/worker';
+
+import './diagnostic.css';
+
+// 修正を適用する。範囲が重ならないよう後ろの編集から適用する
+export function applyFix(text: string, fix: Fix): string {
+  return [...fix.edits]
+    .sort((a, b) => b.from - a.from)
+    .reduce((text, edit) => text.slice(0, edit.from) + edit.text + text.slice(edit.to), text);
+}
+
+// 書き出しが得られないときの，メッセージとヒントだけの書き出し
+function plainReport(diagnostic: Diagnostic): Report {
+  const severity = diagnostic.severity === 1 ? 'error' : 'warning';
+  const lines = [`${severity}: ${diagnostic.message}`, ...diagnostic.hints.map((hint) => `  = hint: ${hint}`)];
+  return { text: lines.join('\n'), html: '' };
+}
 
 export class DiagnosticModal extends Modal {
-  constructor(app: App, diagnosticArray: Diagnostic[]) {
+  // report は診断と同じ順の書き出しを返す
+  constructor(
+    app: App,
+    diagnosticArray: Diagnostic[],
+    onFix?: (fix: Fix) => void,
+    report?: () => Report[] | Promise<Report[]>,
+  ) {
     super(app);
 
-    for (const diagnostic of diagnosticArray) {
-      new Setting(this.contentEl).setName(diagnostic.message).setHeading();
+    const reportEls: HTMLPreElement[] = [];
+    const reports = diagnosticArray.map(plainReport);
 
-      for (const hint of diagnostic.hints) {
-        new Setting(this.contentEl).setName(`hint: ${hint}`);
+    diagnosticArray.forEach((diagnostic, i) => {
+      new Setting(this.contentEl)
+        .setName(diagnostic.message)
+        .setHeading()
+        .addButton((button) => {
+          button.setIcon('copy');
+          button.setTooltip('Copy Diagnostic');
+
+          button.onClick(async () => {
+            await navigator.clipboard.writeText(reports[i]!.text);
+            new Notice('Copied!');
+          });
+        });
+
+      // ? ヒントもソースの抜粋と一緒に書き出される
+      const reportEl = this.contentEl.createEl('pre', { cls: 'typstmate-diag' });
+      reportEl.setText(reports[i]!.text);
+      reportEls.push(reportEl);
+
+      if (!onFix) return;
+      for (const fix of diagnostic.fixes ?? []) {
+        new Setting(this.contentEl).setName(`fix: ${fix.title}`).addButton((button) =>
+          button.setButtonText('Apply').onClick(() => {
+            onFix(fix);
+            this.close();
+          }),
+        );
       }
-    }
+    });
+
+    // ? コンパイルし直すので，開いてから差し替える。診断の数が変わっていたら (編集後など) 使わない
+    if (!report) return;
+    Promise.resolve()
+      .then(report)
+      .then((loaded) => {
+        if (loaded.length !== diagnosticArray.length) return;
+        loaded.forEach((r, i) => {
+          reports[i] = r;
+          if (r.html) reportEls[i]!.replaceChildren(sanitizeHTMLToDom(r.html));
+          else reportEls[i]!.setText(r.text);
+        });
+      })
+      .catch(() => {});
   }
 }
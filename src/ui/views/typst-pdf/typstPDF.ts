@@ -1,9 +1,25 @@
 import { loadPdfJs, type Menu, Notice, TextFileView, type TFile, type WorkspaceLeaf } from 'obsidian';
 
+import type { FileDiagnostics, PDFOptions } from '@/libs/worker';
 import type ObsidianTypstMate from '@/main';
 
 import './typst-pdf.css';
 
+// ファイルごとの警告の一覧 (`path:line:column: message`)
+function warningSummary(files: FileDiagnostics[]): string {
+  const count = files.reduce((sum, file) => sum + file.diags.length, 0);
+  const lines = files.map((file) => {
+    const path = file.package ? `${file.package}/${file.path}` : (file.path ?? '(unknown)');
+    const diags = file.diags.map((diag) => {
+      const start = diag.location?.start;
+      return `  ${start ? `${start.line + 1}:${start.column + 1}: ` : ''}${diag.message}`;
+    });
+    return [path, ...diags].join('\n');
+  });
+
+  return [`exported with ${count} warning${count === 1 ? '' : 's'}`, ...lines].join('\n');
+}
+
 interface PDFViewerState {
   currentPage: number;
   scrollTop: number;
@@ -65,7 +81,7 @@ export class TypstPDFView extends TextFileView {
 
     try {
       this.fileContent = await this.app.vault.read(file);
-      const result = await this.plugin.typst.pdf(file.basename, this.fileContent);
+      const result = await this.plugin.typst.pdf(file.basename, this.fileContent, this.pdfOptions(file));
       this.pdfBinary = result.pdf;
 
       await this.renderPDF(result.pdf, file.basename);
@@ -75,6 +91,8 @@ export class TypstPDFView extends TextFileView {
         const u8arr = new Uint8Array(arrBuffer);
         u8arr.set(result.pdf);
         this.plugin.app.vault.adapter.writeBinary(`${file.path.slice(0, -3)}pdf`, u8arr.buffer);
+
+        if (result.files.length !== 0) new Notice(warningSummary(result.files), 10000);
       });
     } catch {
       new Notice('error');
@@ -91,7 +109,7 @@ export class TypstPDFView extends TextFileView {
 
     try {
       this.fileContent = await this.app.vault.read(file);
-      const result = await this.plugin.typst.pdf(file.basename, this.fileContent);
+      const result = await this.plugin.typst.pdf(file.basename, this.fileContent, this.pdfOptions(file));
       this.pdfBinary = result.pdf;
 
       // ドキュメントを読み込む
@@ -121,6 +139,16 @@ export class TypstPDFView extends TextFileView {
     }
   }
 
+  // .typ ファイルにはフロントマターがないので，タイトルとテーマだけを渡す
+  private pdfOptions(file: TFile): PDFOptions {
+    return {
+      inputs: {
+        title: file.basename,
+        theme: document.body.hasClass('theme-dark') ? 'dark' : 'light',
+      },
+    };
+  }
+
   override async onClose(): Promise<void> {
     this.clearViewerReferences();
     this.pdfDocument = null;
@@ -0,0 +1,163 @@
+use typst::syntax::{LinkedNode, SyntaxKind, SyntaxMode};
+
+use crate::analyzer::enclosing::enclosing_nodes;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyntaxContext {
+    Markup,
+    Math,
+    Code,
+    String,
+    Comment,
+    Raw,
+}
+
+impl SyntaxContext {
+    pub fn parse(context: &str) -> Option<Self> {
+        match context {
+            "markup" => Some(Self::Markup),
+            "math" => Some(Self::Math),
+            "code" => Some(Self::Code),
+            "string" => Some(Self::String),
+            "comment" => Some(Self::Comment),
+            "raw" => Some(Self::Raw),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Markup => "markup",
+            Self::Math => "math",
+            Self::Code => "code",
+            Self::String => "string",
+            Self::Comment => "comment",
+            Self::Raw => "raw",
+        }
+    }
+}
+
+impl From<SyntaxMode> for SyntaxContext {
+    fn from(mode: SyntaxMode) -> Self {
+        match mode {
+            SyntaxMode::Markup => Self::Markup,
+            SyntaxMode::Math => Self::Math,
+            SyntaxMode::Code => Self::Code,
+        }
+    }
+}
+
+pub fn syntax_context(root: &LinkedNode, mode: SyntaxMode, cursor: usize) -> SyntaxContext {
+    let chain = enclosing_nodes(root, cursor);
+    let Some(leaf) = chain.last() else {
+        return mode.into();
+    };
+
+    // 葉そのものが文脈を決める場合
+    let range = leaf.range();
+    let inside = range.start < cursor && cursor < range.end;
+    let unterminated = cursor == range.end && leaf.erroneous();
+    match leaf.kind() {
+        SyntaxKind::Str if inside || unterminated => return SyntaxContext::String,
+        SyntaxKind::LineComment if range.start < cursor => return SyntaxContext::Comment,
+        SyntaxKind::BlockComment if inside || unterminated => return SyntaxContext::Comment,
+        _ => {}
+    }
+
+    // ? 閉じ `$` の直後は数式の外なので，`$` とそれを含む Equation を飛ばす
+    let mut nodes = chain.iter().rev().peekable();
+    if leaf.kind() == SyntaxKind::Dollar && leaf.prev_sibling().is_some() {
+        nodes.next();
+        nodes.next_if(|node| node.kind() == SyntaxKind::Equation);
+    }
+
+    for node in nodes {
+        match node.kind() {
+            SyntaxKind::Raw if cursor < node.range().end || node.erroneous() => {
+                return SyntaxContext::Raw;
+            }
+            SyntaxKind::Math | SyntaxKind::Equation => return SyntaxContext::Math,
+            SyntaxKind::Markup | SyntaxKind::ContentBlock => return SyntaxContext::Markup,
+            SyntaxKind::Code | SyntaxKind::CodeBlock => return SyntaxContext::Code,
+            _ => {}
+        }
+        // `#` に続く埋め込み式
+        if node.prev_sibling_kind() == Some(SyntaxKind::Hash) {
+            return SyntaxContext::Code;
+        }
+    }
+
+    mode.into()
+}
+
+// `math`, `!string` のような条件をカンマ区切りで受け取る
+// 肯定条件のいずれかに一致し，否定条件のいずれにも一致しないときに発火できる
+pub fn match_context(context: SyntaxContext, filter: &str) -> Result<bool, String> {
+    let mut any_positive = false;
+    let mut matched_positive = false;
+
+    for term in filter.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+        let (negated, name) = match term.strip_prefix('!') {
+            Some(name) => (true, name),
+            None => (false, term),
+        };
+        let expected =
+            SyntaxContext::parse(name).ok_or_else(|| format!("unknown context: {}", name))?;
+
+        if negated {
+            if context == expected {
+                return Ok(false);
+            }
+        } else {
+            any_positive = true;
+            matched_positive |= context == expected;
+        }
+    }
+
+    Ok(!any_positive || matched_positive)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::syntax::parse_by_mode;
+
+    // `|` の位置をカーソルとして文脈を求める
+    fn context(code: &str, mode: SyntaxMode) -> SyntaxContext {
+        let cursor = code.find('|').unwrap();
+        let code = code.replacen('|', "", 1);
+        let root = parse_by_mode(&code, mode);
+        syntax_context(&LinkedNode::new(&root), mode, cursor)
+    }
+
+    #[test]
+    fn closing_dollar() {
+        assert_eq!(context("$x|$", SyntaxMode::Markup), SyntaxContext::Math);
+        assert_eq!(context("$x$|", SyntaxMode::Markup), SyntaxContext::Markup);
+        assert_eq!(
+            context("a $x + y$| b", SyntaxMode::Markup),
+            SyntaxContext::Markup
+        );
+        assert_eq!(context("$|x$", SyntaxMode::Markup), SyntaxContext::Math);
+    }
+
+    #[test]
+    fn leaves() {
+        assert_eq!(
+            context("#\"a|b\"", SyntaxMode::Markup),
+            SyntaxContext::String
+        );
+        assert_eq!(context("#\"ab\"|", SyntaxMode::Markup), SyntaxContext::Code);
+        assert_eq!(context("// a|", SyntaxMode::Markup), SyntaxContext::Comment);
+        assert_eq!(context("`a|`", SyntaxMode::Markup), SyntaxContext::Raw);
+        assert_eq!(context("x + |", SyntaxMode::Math), SyntaxContext::Math);
+    }
+
+    #[test]
+    fn filters() {
+        assert_eq!(match_context(SyntaxContext::Math, "math,!string"), Ok(true));
+        assert_eq!(match_context(SyntaxContext::String, "!string"), Ok(false));
+        assert_eq!(match_context(SyntaxContext::Code, "math"), Ok(false));
+        assert!(match_context(SyntaxContext::Code, "maths").is_err());
+    }
+}
@@ -0,0 +1,22 @@
+use typst::syntax::{LinkedNode, Side};
+
+// 外側から内側の順に，カーソルを含むノードを返す
+pub fn enclosing_nodes<'a>(root: &LinkedNode<'a>, cursor: usize) -> Vec<LinkedNode<'a>> {
+    // ? 入力中の文脈を知りたいので，カーソル直前の葉を優先する
+    let leaf = root
+        .leaf_at(cursor, Side::Before)
+        .or_else(|| root.leaf_at(cursor, Side::After));
+
+    let mut chain = Vec::new();
+    let mut node = leaf;
+    while let Some(n) = node {
+        node = n.parent().cloned();
+        chain.push(n);
+    }
+    if chain.is_empty() {
+        chain.push(root.clone());
+    }
+    chain.reverse();
+
+    chain
+}
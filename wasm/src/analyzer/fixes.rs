@@ -0,0 +1,310 @@
+use std::ops::Range;
+
+use typst::{
+    Library, World,
+    diag::SourceDiagnostic,
+    foundations::{Symbol, Value},
+    syntax::{LinkedNode, Source, SyntaxKind},
+};
+
+use crate::utils::symbols::latex_to_typst;
+
+// ソース中のバイト範囲を置き換える
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fix {
+    pub title: String,
+    pub edits: Vec<TextEdit>,
+}
+
+// 候補の最大数
+const MAX_CANDIDATES: usize = 3;
+
+// 数式中で `#` を付け忘れやすいキーワード
+const KEYWORDS: [&str; 9] = [
+    "let", "set", "show", "if", "for", "while", "import", "include", "context",
+];
+
+// 診断に対する機械的に適用できる修正
+// ? `source` は診断の span を含むファイル
+pub fn fixes(diag: &SourceDiagnostic, source: &Source, library: &Library) -> Vec<Fix> {
+    let Some(node) = LinkedNode::new(source.root()).find(diag.span) else {
+        return Vec::new();
+    };
+    let message = diag.message.as_str();
+
+    if let Some(name) = message.strip_prefix("unknown variable: ") {
+        let hash = diag
+            .hints
+            .iter()
+            .any(|hint| hint.contains("try adding a hash before it"));
+        if hash || (node.kind() == SyntaxKind::MathIdent && KEYWORDS.contains(&name)) {
+            return vec![Fix {
+                title: format!("Add `#` before `{name}`"),
+                edits: vec![TextEdit {
+                    range: node.offset()..node.offset(),
+                    text: "#".to_string(),
+                }],
+            }];
+        }
+
+        if node.kind() == SyntaxKind::MathIdent {
+            return latex_fixes(source.text(), node.range(), name, library);
+        }
+    } else if message == "unclosed delimiter" {
+        return closing_fix(source.text(), &node).into_iter().collect();
+    } else if message == "unknown symbol modifier" {
+        return modifier_fixes(source.text(), &node, library);
+    }
+
+    Vec::new()
+}
+
+// メインファイル中の診断に対する修正
+pub fn main_fixes<W>(diag: &SourceDiagnostic, world: &W) -> Vec<Fix>
+where
+    W: World,
+{
+    if diag.span.id() != Some(world.main()) {
+        return Vec::new();
+    }
+
+    match world.source(world.main()) {
+        Ok(source) => fixes(diag, &source, world.library()),
+        Err(_) => Vec::new(),
+    }
+}
+
+// LaTeX のコマンドを Typst の記号に置き換える
+fn latex_fixes(text: &str, range: Range<usize>, name: &str, library: &Library) -> Vec<Fix> {
+    // ? `\infty` は `\i` のエスケープと `nfty` に分かれる
+    let mut before = text[..range.start].chars();
+    let (command, start) = match (before.next_back(), before.next_back()) {
+        (Some(c), Some('\\')) => (format!("{c}{name}"), range.start - 1 - c.len_utf8()),
+        _ => (name.to_string(), range.start),
+    };
+
+    let mut candidates = Vec::new();
+    if resolve(library, &command).is_some() {
+        candidates.push(command.clone());
+    }
+    candidates.extend(
+        latex_to_typst(&command)
+            .iter()
+            .filter(|name| is_ident_path(name) && resolve(library, name).is_some())
+            .filter(|name| **name != command)
+            .cloned(),
+    );
+
+    let typed = &text[start..range.end];
+    candidates
+        .into_iter()
+        .take(MAX_CANDIDATES)
+        .map(|candidate| Fix {
+            title: format!("Replace `{typed}` with `{candidate}`"),
+            edits: vec![TextEdit {
+                range: start..range.end,
+                text: candidate,
+            }],
+        })
+        .collect()
+}
+
+// 閉じ忘れた括弧を，括弧を含むノードの末尾 (空白の前) に補う
+fn closing_fix(text: &str, node: &LinkedNode) -> Option<Fix> {
+    let closer = match node.text().as_str() {
+        "(" => ")",
+        "[" => "]",
+        "{" => "}",
+        "$" => "$",
+        _ => return None,
+    };
+    let parent = node.parent()?;
+    // ? 閉じていない文字列などが他にあると，どこで閉じるべきか分からない
+    if parent.get().errors().len() > 1 {
+        return None;
+    }
+
+    let start = node.range().end;
+    let end = start + text[start..parent.range().end].trim_end().len();
+
+    Some(Fix {
+        title: format!("Insert missing `{closer}`"),
+        edits: vec![TextEdit {
+            range: end..end,
+            text: closer.to_string(),
+        }],
+    })
+}
+
+// 存在しない修飾子を，入力に近い記号名に置き換える
+fn modifier_fixes(text: &str, node: &LinkedNode, library: &Library) -> Vec<Fix> {
+    // `arrow.rr` のフィールドアクセス全体
+    let mut access = node.clone();
+    while let Some(parent) = access.parent()
+        && parent.kind() == SyntaxKind::FieldAccess
+    {
+        access = parent.clone();
+    }
+    if access.kind() != SyntaxKind::FieldAccess {
+        return Vec::new();
+    }
+
+    let range = access.range();
+    let typed = &text[range.clone()];
+    let mut segments = typed.split('.');
+    let Some(root) = segments.next() else {
+        return Vec::new();
+    };
+
+    // モジュールを辿って記号まで進む
+    let mut prefix = root.to_string();
+    let mut value = lookup(library, root);
+    let symbol = loop {
+        match value {
+            Some(Value::Module(module)) => {
+                let Some(segment) = segments.next() else {
+                    return Vec::new();
+                };
+                value = module.scope().get(segment).map(|b| b.read().clone());
+                prefix = format!("{prefix}.{segment}");
+            }
+            Some(Value::Symbol(symbol)) => break symbol,
+            _ => return Vec::new(),
+        }
+    };
+
+    let mut candidates: Vec<String> = symbol
+        .variants()
+        .map(|(modifiers, _, _)| match modifiers.as_str() {
+            "" => prefix.clone(),
+            modifiers => format!("{prefix}.{modifiers}"),
+        })
+        .collect();
+    candidates.sort_by_key(|candidate| (levenshtein(typed, candidate), candidate.len()));
+    candidates.dedup();
+
+    candidates
+        .into_iter()
+        .take(MAX_CANDIDATES)
+        .map(|candidate| Fix {
+            title: format!("Replace `{typed}` with `{candidate}`"),
+            edits: vec![TextEdit {
+                range: range.clone(),
+                text: candidate,
+            }],
+        })
+        .collect()
+}
+
+// 数式中で使える名前を，数式のスコープ・グローバルスコープの順に探す
+fn lookup(library: &Library, name: &str) -> Option<Value> {
+    library
+        .math
+        .scope()
+        .get(name)
+        .or_else(|| library.global.scope().get(name))
+        .map(|binding| binding.read().clone())
+}
+
+// `arrow.r.long` のような名前を解決する
+fn resolve(library: &Library, name: &str) -> Option<Value> {
+    let mut segments = name.split('.');
+    let mut value = lookup(library, segments.next()?)?;
+
+    for segment in segments {
+        value = match value {
+            Value::Module(module) => module.scope().get(segment)?.read().clone(),
+            Value::Symbol(symbol) => Value::Symbol(Symbol::modified(symbol, (), segment).ok()?),
+            _ => return None,
+        };
+    }
+
+    Some(value)
+}
+
+// ? シンボルデータには `<=` のような略記も含まれる
+fn is_ident_path(name: &str) -> bool {
+    name.split('.').all(|segment| {
+        let mut chars = segment.chars();
+        chars.next().is_some_and(char::is_alphabetic) && chars.all(char::is_alphanumeric)
+    })
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (above + 1)
+                .min(row[j] + 1)
+                .min(diagonal + usize::from(ca != *cb));
+            diagonal = above;
+        }
+    }
+
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::{CompileError, Compiler};
+    use crate::provider::MemoryFileProvider;
+
+    // 最初のエラーに対する修正をそれぞれ適用したソース
+    fn fixed(code: &str) -> Vec<String> {
+        let mut compiler = Compiler::new(Box::new(MemoryFileProvider::new()), 16.0);
+        let Err(CompileError::Diagnostics(errors)) = compiler.svg(code, "inline", "test") else {
+            panic!("expected an error: {code}");
+        };
+
+        main_fixes(&errors[0], compiler.world())
+            .into_iter()
+            .map(|fix| {
+                let mut text = code.to_string();
+                for edit in fix.edits.iter().rev() {
+                    text.replace_range(edit.range.clone(), &edit.text);
+                }
+                text
+            })
+            .collect()
+    }
+
+    #[test]
+    fn latex_command() {
+        assert_eq!(fixed("$\\alpha + 1$")[0], "$alpha + 1$");
+        assert!(fixed("$\\infty$").contains(&"$infinity$".to_string()));
+    }
+
+    #[test]
+    fn missing_hash() {
+        assert_eq!(fixed("$x + let$"), ["$x + #let$"]);
+    }
+
+    #[test]
+    fn unclosed_delimiter() {
+        assert_eq!(fixed("#let f(x) = x\n#f(1 \n"), ["#let f(x) = x\n#f(1) \n"]);
+    }
+
+    #[test]
+    fn symbol_modifier() {
+        assert_eq!(fixed("$arrow.rr$")[0], "$arrow.r$");
+    }
+
+    #[test]
+    fn distance() {
+        assert_eq!(levenshtein("arrow.rr", "arrow.r"), 1);
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("", "abc"), 3);
+    }
+}
@@ -0,0 +1,60 @@
+use std::{ops::Range, str::FromStr};
+
+use typst::syntax::{
+    LinkedNode, SyntaxKind, ast,
+    package::{PackageSpec, VersionlessPackageSpec},
+};
+
+#[derive(Debug, Clone)]
+pub enum ImportTarget {
+    Package(PackageSpec),
+    // `@preview/name` のようにバージョンがない
+    Versionless(VersionlessPackageSpec),
+    // `@` で始まるが解釈できない
+    Invalid,
+}
+
+#[derive(Debug, Clone)]
+pub struct PackageImport {
+    pub source: String,
+    pub target: ImportTarget,
+    // 文字列リテラル (引用符を含む) の範囲
+    pub range: Range<usize>,
+}
+
+// `#import "@..."` と `#include "@..."` を集める
+pub fn package_imports(root: &LinkedNode) -> Vec<PackageImport> {
+    let mut ret = Vec::new();
+    collect(root, &mut ret);
+    ret
+}
+
+fn collect(node: &LinkedNode, ret: &mut Vec<PackageImport>) {
+    if matches!(
+        node.kind(),
+        SyntaxKind::ModuleImport | SyntaxKind::ModuleInclude
+    ) && let Some(lit) = node.children().find(|c| c.kind() == SyntaxKind::Str)
+        && let Some(s) = lit.get().cast::<ast::Str>()
+    {
+        let source = s.get().to_string();
+        if source.starts_with('@') {
+            let target = if let Ok(spec) = PackageSpec::from_str(&source) {
+                ImportTarget::Package(spec)
+            } else if let Ok(spec) = VersionlessPackageSpec::from_str(&source) {
+                ImportTarget::Versionless(spec)
+            } else {
+                ImportTarget::Invalid
+            };
+
+            ret.push(PackageImport {
+                source,
+                target,
+                range: lit.range(),
+            });
+        }
+    }
+
+    for child in node.children() {
+        collect(&child, ret);
+    }
+}
@@ -0,0 +1,404 @@
+use std::ops::Range;
+
+use typst::syntax::{LinkedNode, SyntaxKind};
+
+use crate::analyzer::enclosing::enclosing_nodes;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableKind {
+    Mat,   // 行は `;`，列は `,` 区切り
+    Cases, // 行は `,`，列は `&` 区切り
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum TableOp {
+    InsertRowAbove,
+    InsertRowBelow,
+    DeleteRow,
+    InsertColumnLeft,
+    InsertColumnRight,
+    DeleteColumn,
+    Transpose,
+    Align,
+}
+
+impl TableOp {
+    pub fn parse(op: &str) -> Option<Self> {
+        match op {
+            "insert_row_above" => Some(Self::InsertRowAbove),
+            "insert_row_below" => Some(Self::InsertRowBelow),
+            "delete_row" => Some(Self::DeleteRow),
+            "insert_column_left" => Some(Self::InsertColumnLeft),
+            "insert_column_right" => Some(Self::InsertColumnRight),
+            "delete_column" => Some(Self::DeleteColumn),
+            "transpose" => Some(Self::Transpose),
+            "align" => Some(Self::Align),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Table {
+    pub kind: TableKind,
+    pub callee: String,
+    pub named: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+    pub multiline: bool,
+    pub indent: String,
+    pub range: Range<usize>,
+
+    // カーソルのあるセル
+    pub row: usize,
+    pub col: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct TableEdit {
+    pub text: String,
+    pub cursor: usize,
+}
+
+fn node_text(node: &LinkedNode) -> String {
+    node.get().clone().into_text().trim().to_string()
+}
+
+// カーソルを含む最も内側の `mat(...)` または `cases(...)` を探す
+pub fn find_table(root: &LinkedNode, code: &str, cursor: usize) -> Option<Table> {
+    let call = enclosing_nodes(root, cursor)
+        .into_iter()
+        .rev()
+        .find(|n| n.kind() == SyntaxKind::FuncCall && table_kind(n).is_some())?;
+    let kind = table_kind(&call)?;
+    let callee = node_text(&call.children().next()?);
+    let args = call.children().find(|c| c.kind() == SyntaxKind::Args)?;
+
+    let mut named = Vec::new();
+    let mut rows: Vec<Vec<(String, Range<usize>)>> = Vec::new();
+    let mut row = Vec::new();
+    for child in args.children() {
+        match (kind, child.kind()) {
+            (_, SyntaxKind::LeftParen | SyntaxKind::RightParen | SyntaxKind::Comma) => {}
+            _ if child.kind().is_trivia() => {}
+            // 展開される要素数が分からないので編集しない
+            (_, SyntaxKind::Spread) => return None,
+            (_, SyntaxKind::Named) => named.push(node_text(&child)),
+            (TableKind::Mat, SyntaxKind::Semicolon) => rows.push(std::mem::take(&mut row)),
+            (TableKind::Mat, SyntaxKind::Array) => row.extend(
+                child
+                    .children()
+                    .filter(|c| !c.kind().is_trivia() && c.kind() != SyntaxKind::Comma)
+                    .map(|c| (node_text(&c), c.range())),
+            ),
+            (TableKind::Mat, _) => row.push((node_text(&child), child.range())),
+            (TableKind::Cases, _) => rows.push(split_align_points(&child)),
+        }
+    }
+    if !row.is_empty() {
+        rows.push(row);
+    }
+    if rows.is_empty() {
+        rows.push(vec![(String::new(), args.range())]);
+    }
+
+    // カーソルより前で最後に始まるセル
+    let (mut cur_row, mut cur_col) = (0, 0);
+    for (i, cells) in rows.iter().enumerate() {
+        for (j, (_, range)) in cells.iter().enumerate() {
+            if range.start <= cursor {
+                (cur_row, cur_col) = (i, j);
+            }
+        }
+    }
+
+    let line_start = code[..call.offset()].rfind('\n').map_or(0, |i| i + 1);
+    let indent = code[line_start..call.offset()]
+        .chars()
+        .take_while(|c| c.is_whitespace())
+        .collect();
+
+    Some(Table {
+        kind,
+        callee,
+        named,
+        rows: rows
+            .into_iter()
+            .map(|cells| cells.into_iter().map(|(text, _)| text).collect())
+            .collect(),
+        multiline: node_text(&args).contains('\n'),
+        indent,
+        range: call.range(),
+
+        row: cur_row,
+        col: cur_col,
+    })
+}
+
+fn table_kind(call: &LinkedNode) -> Option<TableKind> {
+    let callee = call.children().next()?;
+    match callee.get().text().as_str() {
+        "mat" => Some(TableKind::Mat),
+        "cases" => Some(TableKind::Cases),
+        _ => None,
+    }
+}
+
+fn split_align_points(arg: &LinkedNode) -> Vec<(String, Range<usize>)> {
+    if arg.kind() != SyntaxKind::Math {
+        return vec![(node_text(arg), arg.range())];
+    }
+
+    let mut cells = Vec::new();
+    let mut start = arg.offset();
+    for child in arg.children() {
+        if child.kind() == SyntaxKind::MathAlignPoint {
+            cells.push(start..child.offset());
+            start = child.range().end;
+        }
+    }
+    cells.push(start..arg.range().end);
+
+    let base = arg.offset();
+    let full = arg.get().clone().into_text();
+    cells
+        .into_iter()
+        .map(|r| (full[r.start - base..r.end - base].trim().to_string(), r))
+        .collect()
+}
+
+impl Table {
+    fn width(&self) -> usize {
+        self.rows.iter().map(Vec::len).max().unwrap_or(0)
+    }
+
+    // 列数を揃える
+    fn normalize(&mut self) {
+        let width = self.width().max(1);
+        for cells in self.rows.iter_mut() {
+            cells.resize(width, String::new());
+        }
+    }
+
+    pub fn apply(&mut self, op: TableOp) {
+        self.normalize();
+        let width = self.width();
+
+        match op {
+            TableOp::InsertRowAbove => {
+                self.rows.insert(self.row, vec![String::new(); width]);
+                self.col = 0;
+            }
+            TableOp::InsertRowBelow => {
+                self.row += 1;
+                self.rows.insert(self.row, vec![String::new(); width]);
+                self.col = 0;
+            }
+            TableOp::DeleteRow => {
+                if self.rows.len() > 1 {
+                    self.rows.remove(self.row);
+                    self.row = self.row.min(self.rows.len() - 1);
+                } else {
+                    self.rows[0].iter_mut().for_each(String::clear);
+                }
+            }
+            TableOp::InsertColumnLeft => {
+                self.rows
+                    .iter_mut()
+                    .for_each(|cells| cells.insert(self.col, String::new()));
+            }
+            TableOp::InsertColumnRight => {
+                self.col += 1;
+                self.rows
+                    .iter_mut()
+                    .for_each(|cells| cells.insert(self.col, String::new()));
+            }
+            TableOp::DeleteColumn => {
+                if width > 1 {
+                    self.rows.iter_mut().for_each(|cells| {
+                        cells.remove(self.col);
+                    });
+                    self.col = self.col.min(width - 2);
+                } else {
+                    self.rows.iter_mut().for_each(|cells| cells[0].clear());
+                }
+            }
+            TableOp::Transpose => {
+                self.rows = (0..width)
+                    .map(|j| self.rows.iter().map(|cells| cells[j].clone()).collect())
+                    .collect();
+                (self.row, self.col) = (self.col, self.row);
+            }
+            TableOp::Align => {}
+        }
+    }
+
+    // 整形したテキストと，カーソルのあるセルの先頭オフセット (呼び出しの先頭から) を返す
+    pub fn render(&self, align: bool) -> (String, usize) {
+        let (cell_sep, row_sep) = match self.kind {
+            TableKind::Mat => (", ", ";"),
+            TableKind::Cases => (" & ", ","),
+        };
+
+        let widths: Vec<usize> = (0..self.width())
+            .map(|j| {
+                self.rows
+                    .iter()
+                    .filter_map(|cells| cells.get(j))
+                    .map(|c| c.chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        let mut text = format!("{}(", self.callee);
+        let mut cursor = text.len();
+        let row_indent = format!("{}  ", self.indent);
+
+        let mut args: Vec<String> = self.named.clone();
+        let named_len = args.len();
+        let mut row_cursors = Vec::new();
+        for (i, cells) in self.rows.iter().enumerate() {
+            let mut row = String::new();
+            for (j, cell) in cells.iter().enumerate() {
+                if j > 0 {
+                    row.push_str(cell_sep);
+                }
+                if i == self.row && j == self.col {
+                    row_cursors.push(row.len());
+                }
+                row.push_str(cell);
+                // ? 最後の列は末尾の空白を付けない
+                if align && j + 1 < cells.len() {
+                    let pad = widths[j].saturating_sub(cell.chars().count());
+                    row.push_str(&" ".repeat(pad));
+                }
+            }
+            args.push(row);
+        }
+
+        for (k, arg) in args.iter().enumerate() {
+            let is_row = k >= named_len;
+            if self.multiline {
+                text.push('\n');
+                text.push_str(&row_indent);
+            } else if k > 0 {
+                text.push(' ');
+            }
+            if is_row && k - named_len == self.row {
+                cursor = text.len() + row_cursors.first().copied().unwrap_or(0);
+            }
+            text.push_str(arg);
+
+            let last = k + 1 == args.len();
+            if !last {
+                text.push_str(if is_row { row_sep } else { "," });
+            } else if self.multiline {
+                text.push_str(row_sep);
+            }
+        }
+        if self.multiline {
+            text.push('\n');
+            text.push_str(&self.indent);
+        }
+        text.push(')');
+
+        (text, cursor)
+    }
+}
+
+pub fn edit_table(root: &LinkedNode, code: &str, cursor: usize, op: TableOp) -> Option<TableEdit> {
+    let mut table = find_table(root, code, cursor)?;
+    table.apply(op);
+
+    let align = matches!(op, TableOp::Align) || table.multiline;
+    let (rendered, offset) = table.render(align);
+
+    let mut text = String::with_capacity(code.len() + rendered.len());
+    text.push_str(&code[..table.range.start]);
+    text.push_str(&rendered);
+    text.push_str(&code[table.range.end..]);
+
+    Some(TableEdit {
+        text,
+        cursor: table.range.start + offset,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use typst::syntax::SyntaxMode;
+
+    use super::*;
+    use crate::parser::syntax::parse_by_mode;
+
+    // `|` の位置をカーソルとして編集し，編集後のカーソルも `|` で示す
+    fn edit(code: &str, op: TableOp) -> Option<String> {
+        let cursor = code.find('|').unwrap();
+        let code = code.replacen('|', "", 1);
+        let root = parse_by_mode(&code, SyntaxMode::Math);
+        let edit = edit_table(&LinkedNode::new(&root), &code, cursor, op)?;
+
+        let mut text = edit.text;
+        text.insert(edit.cursor, '|');
+        Some(text)
+    }
+
+    #[test]
+    fn mat() {
+        let code = "mat(a, |b; c, d)";
+        assert_eq!(
+            edit(code, TableOp::InsertRowBelow).unwrap(),
+            "mat(a, b; |, ; c, d)"
+        );
+        assert_eq!(edit(code, TableOp::DeleteColumn).unwrap(), "mat(|a; c)");
+        assert_eq!(
+            edit(code, TableOp::InsertColumnRight).unwrap(),
+            "mat(a, b, |; c, d, )"
+        );
+        assert_eq!(edit(code, TableOp::Transpose).unwrap(), "mat(a, c; |b, d)");
+        assert_eq!(
+            edit("mat(delim: \"[\", |a; b)", TableOp::DeleteRow).unwrap(),
+            "mat(delim: \"[\", |b)"
+        );
+    }
+
+    #[test]
+    fn cases() {
+        assert_eq!(
+            edit("cases(x &|= 1, y &= 2)", TableOp::InsertColumnLeft).unwrap(),
+            "cases(x & | & = 1, y &  & = 2)"
+        );
+    }
+
+    #[test]
+    fn multiline() {
+        let code = "mat(\n  1, 10;\n  |100, 2;\n)";
+        assert_eq!(
+            edit(code, TableOp::Align).unwrap(),
+            "mat(\n  1  , 10;\n  |100, 2;\n)"
+        );
+    }
+
+    // コメントはセルとして扱わない
+    #[test]
+    fn trivia() {
+        assert_eq!(
+            edit("mat(|a, b; // note\n c, d)", TableOp::Align).unwrap(),
+            "mat(\n  |a, b;\n  c, d;\n)"
+        );
+        assert_eq!(
+            edit("mat(a, b /* x */; |c, d)", TableOp::Transpose).unwrap(),
+            "mat(a, |c; b, d)"
+        );
+    }
+
+    #[test]
+    fn spread() {
+        assert!(edit("mat(..args, |a)", TableOp::Transpose).is_none());
+    }
+
+    #[test]
+    fn outside() {
+        assert!(edit("x + |y", TableOp::Align).is_none());
+    }
+}
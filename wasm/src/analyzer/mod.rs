@@ -0,0 +1,6 @@
+pub mod context;
+pub mod enclosing;
+pub mod fixes;
+pub mod imports;
+pub mod matrix;
+pub mod selection;
@@ -0,0 +1,60 @@
+use std::ops::Range;
+
+use typst::syntax::{LinkedNode, Side, SyntaxKind};
+
+// 内側から外側の順に，`from` を含む構文上の範囲を返す
+fn ladder(root: &LinkedNode, from: usize) -> Vec<Range<usize>> {
+    let leaf = root
+        .leaf_at(from, Side::After)
+        .or_else(|| root.leaf_at(from, Side::Before));
+
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    let mut node = leaf;
+    while let Some(n) = node {
+        if !n.kind().is_trivia() {
+            // ? 括弧の中身だけを選ぶ段階を挟む
+            if let Some(inner) = inner_range(&n) {
+                ranges.push(inner);
+            }
+            ranges.push(n.range());
+        }
+        node = n.parent().cloned();
+    }
+    ranges.push(root.range());
+    ranges.dedup();
+
+    ranges
+}
+
+fn inner_range(node: &LinkedNode) -> Option<Range<usize>> {
+    let first = node.children().next()?;
+    let last = node.children().next_back()?;
+    if first.range() == last.range() {
+        return None;
+    }
+
+    let delimited = matches!(
+        (first.kind(), last.kind()),
+        (SyntaxKind::LeftParen, SyntaxKind::RightParen)
+            | (SyntaxKind::LeftBracket, SyntaxKind::RightBracket)
+            | (SyntaxKind::LeftBrace, SyntaxKind::RightBrace)
+            | (SyntaxKind::Dollar, SyntaxKind::Dollar)
+    );
+
+    delimited.then(|| first.range().end..last.range().start)
+}
+
+pub fn expand_selection(root: &LinkedNode, from: usize, to: usize) -> Range<usize> {
+    ladder(root, from)
+        .into_iter()
+        .find(|r| r.start <= from && to <= r.end && (r.start, r.end) != (from, to))
+        .unwrap_or(from..to)
+}
+
+pub fn shrink_selection(root: &LinkedNode, from: usize, to: usize) -> Range<usize> {
+    ladder(root, from)
+        .into_iter()
+        .rev()
+        .find(|r| from <= r.start && r.end <= to && (r.start, r.end) != (from, to))
+        .unwrap_or(from..from)
+}
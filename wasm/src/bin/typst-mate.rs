@@ -0,0 +1,630 @@
+// Obsidian の保管庫を描画・検査する CLI
+// 数式と `.typ` ファイルをプラグインと同じパイプラインで SVG/PNG/PDF にする
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use typst::{
+    World,
+    diag::{Severity, SourceDiagnostic},
+    foundations::{Dict, IntoValue},
+    layout::Abs,
+    syntax::FileId,
+};
+use walkdir::WalkDir;
+
+use typst_wasm::{
+    CompileError, Compiled, Compiler,
+    provider::FsFileProvider,
+    template::Origin,
+    utils::package::{file_key, parse_package_key},
+    vault::{lint::locate, markdown::math_segments, settings::Settings},
+};
+
+const USAGE: &str = "\
+usage: typst-mate <vault> [options]
+
+options:
+  -o, --out <dir>          write rendered files to <dir> (omit to only check)
+  -f, --format <format>    svg, png or pdf (default: svg)
+      --data <path>        plugin settings (default: <vault>/<config-dir>/plugins/typst-mate/data.json)
+      --config-dir <name>  Obsidian config directory (default: .obsidian)
+      --package-path <dir> additional local package directory (repeatable)
+      --font-path <dir>    additional font directory (repeatable)
+      --fontsize <pt>      base font size of the vault (default: 16)
+      --base-color <color> color substituted for the theme color (png default: #000000)
+      --scale <n>          pixels per point for png (default: 2)
+      --input <key=value>  add a string to `sys.inputs` (repeatable, `title` defaults to the file name)
+      --deny-warnings      exit with failure on warnings
+  -h, --help               print this help";
+
+// 診断の位置 (`path:line:column`) と，数式の外が原因ならその注記
+type Locate<'a> = dyn Fn(&Compiler, &SourceDiagnostic) -> (String, Option<&'static str>) + 'a;
+
+const BASE_COLOR_VAR: &str = "var(--typst-base-color)";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Svg,
+    Png,
+    Pdf,
+}
+
+impl Format {
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Svg => "svg",
+            Format::Png => "png",
+            Format::Pdf => "pdf",
+        }
+    }
+}
+
+struct Args {
+    vault: PathBuf,
+    out: Option<PathBuf>,
+    format: Format,
+    data: Option<PathBuf>,
+    config_dir: String,
+    package_paths: Vec<PathBuf>,
+    font_paths: Vec<PathBuf>,
+    fontsize: f64,
+    base_color: Option<String>,
+    scale: f32,
+    // `--input` で指定した `sys.inputs`
+    inputs: Vec<(String, String)>,
+    deny_warnings: bool,
+}
+
+#[derive(Default)]
+struct Report {
+    rendered: usize,
+    errors: usize,
+    warnings: usize,
+    // --deny-warnings か failOnWarning
+    deny_warnings: bool,
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(message) => {
+            eprintln!("error: {message}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+
+    match run(&args) {
+        Ok(report) => {
+            eprintln!(
+                "{} rendered, {} error(s), {} warning(s)",
+                report.rendered, report.errors, report.warnings
+            );
+
+            if report.errors != 0 || (report.deny_warnings && report.warnings != 0) {
+                ExitCode::from(1)
+            } else {
+                ExitCode::SUCCESS
+            }
+        }
+        Err(message) => {
+            eprintln!("error: {message}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn parse_args(mut iter: impl Iterator<Item = String>) -> Result<Option<Args>, String> {
+    let mut vault = None;
+    let mut args = Args {
+        vault: PathBuf::new(),
+        out: None,
+        format: Format::Svg,
+        data: None,
+        config_dir: ".obsidian".to_string(),
+        package_paths: Vec::new(),
+        font_paths: Vec::new(),
+        fontsize: 16.0,
+        base_color: None,
+        scale: 2.0,
+        inputs: Vec::new(),
+        deny_warnings: false,
+    };
+
+    while let Some(arg) = iter.next() {
+        let mut value = || {
+            iter.next()
+                .ok_or_else(|| format!("missing value for {arg}"))
+        };
+
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-o" | "--out" => args.out = Some(value()?.into()),
+            "-f" | "--format" => {
+                args.format = match value()?.as_str() {
+                    "svg" => Format::Svg,
+                    "png" => Format::Png,
+                    "pdf" => Format::Pdf,
+                    other => return Err(format!("unknown format: {other}")),
+                }
+            }
+            "--data" => args.data = Some(value()?.into()),
+            "--config-dir" => args.config_dir = value()?,
+            "--package-path" => args.package_paths.push(value()?.into()),
+            "--font-path" => args.font_paths.push(value()?.into()),
+            "--fontsize" => {
+                args.fontsize = value()?
+                    .parse()
+                    .map_err(|_| "--fontsize expects a number".to_string())?
+            }
+            "--base-color" => args.base_color = Some(value()?),
+            "--scale" => {
+                args.scale = value()?
+                    .parse()
+                    .ok()
+                    .filter(|s: &f32| *s > 0.0)
+                    .ok_or_else(|| "--scale expects a positive number".to_string())?
+            }
+            "--input" => {
+                let input = value()?;
+                let (key, value) = input
+                    .split_once('=')
+                    .ok_or_else(|| format!("--input expects key=value: {input}"))?;
+                args.inputs.push((key.to_string(), value.to_string()));
+            }
+            "--deny-warnings" => args.deny_warnings = true,
+            _ if arg.starts_with('-') => return Err(format!("unknown option: {arg}")),
+            _ if vault.is_none() => vault = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument: {arg}")),
+        }
+    }
+
+    args.vault = vault.ok_or("missing vault directory")?;
+    Ok(Some(args))
+}
+
+fn run(args: &Args) -> Result<Report, String> {
+    if !args.vault.is_dir() {
+        return Err(format!("{} is not a directory", args.vault.display()));
+    }
+    let plugin_dir = args
+        .vault
+        .join(&args.config_dir)
+        .join("plugins")
+        .join("typst-mate");
+
+    let settings = load_settings(args.data.as_deref(), &plugin_dir)?;
+
+    // ローカルパッケージ
+    let mut package_dirs = vec![plugin_dir.join("packages")];
+    package_dirs.extend(args.package_paths.iter().cloned());
+    let provider = FsFileProvider::new(&args.vault, package_dirs);
+    let mut compiler = Compiler::new(Box::new(provider), args.fontsize);
+
+    // フォント
+    let mut font_dirs = vec![plugin_dir.join("fonts")];
+    font_dirs.extend(args.font_paths.iter().cloned());
+    for path in font_dirs.iter().flat_map(|dir| files_in(dir)) {
+        let is_font = path.extension().and_then(|e| e.to_str()).is_some_and(|e| {
+            matches!(
+                e.to_ascii_lowercase().as_str(),
+                "font" | "ttf" | "otf" | "ttc" | "otc"
+            )
+        });
+        if is_font {
+            let data = fs::read(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+            compiler.add_font(data);
+        }
+    }
+
+    // パッケージのキャッシュ
+    if !settings.disable_package_cache {
+        for path in files_in(&plugin_dir.join("caches")) {
+            if path.extension().is_some_and(|e| e == "cache") {
+                load_cache(&mut compiler, &path)?;
+            }
+        }
+    }
+
+    let mut report = Report {
+        deny_warnings: args.deny_warnings || settings.fail_on_warning,
+        ..Default::default()
+    };
+
+    let walker = WalkDir::new(&args.vault)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|e| {
+            // ? 設定フォルダや隠しフォルダ，出力先は対象外
+            e.depth() == 0
+                || !(e.file_name().to_string_lossy().starts_with('.')
+                    || args
+                        .out
+                        .as_deref()
+                        .is_some_and(|out| same_path(e.path(), out)))
+        });
+    for entry in walker {
+        let entry = entry.map_err(|e| e.to_string())?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        let path = entry.path();
+        let rel = path.strip_prefix(&args.vault).unwrap_or(path);
+        compiler.set_inputs(inputs(args, path));
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("md") => {
+                render_note(&mut compiler, &settings, args, path, rel, &mut report)?;
+            }
+            Some("typ") => {
+                render_typ(&mut compiler, args, path, rel, &mut report)?;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(report)
+}
+
+fn load_settings(data: Option<&Path>, plugin_dir: &Path) -> Result<Settings, String> {
+    let path = match data {
+        Some(path) => path.to_path_buf(),
+        None => {
+            let path = plugin_dir.join("data.json");
+            if !path.exists() {
+                return Ok(Settings::default());
+            }
+            path
+        }
+    };
+
+    let json = fs::read_to_string(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+    serde_json::from_str(&json).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+fn files_in(dir: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.is_file())
+        .collect();
+    paths.sort();
+
+    paths
+}
+
+fn same_path(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+// プラグインの `.cache` (packageCompressor.ts の zip 形式) を読み込む
+fn load_cache(compiler: &mut Compiler, path: &Path) -> Result<(), String> {
+    let bytes = fs::read(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let entries = unzip_cache(&bytes)
+        .ok_or_else(|| format!("{}: malformed package cache", path.display()))?;
+
+    for (key, data) in entries {
+        let Some(data) = data else { continue };
+        let key = format!("@{}", key.trim_start_matches(['/', '\\']));
+        match parse_package_key(&key) {
+            Ok((spec, vpath)) => compiler.world_mut().add_package_file(spec, vpath, data),
+            Err(message) => eprintln!("warning: {}: {message}", path.display()),
+        }
+    }
+
+    Ok(())
+}
+
+fn unzip_cache(bytes: &[u8]) -> Option<Vec<(String, Option<Vec<u8>>)>> {
+    let mut offset = 0;
+    let read_u32 = |offset: &mut usize| -> Option<usize> {
+        let value = u32::from_le_bytes(bytes.get(*offset..*offset + 4)?.try_into().ok()?);
+        *offset += 4;
+        Some(value as usize)
+    };
+
+    let count = read_u32(&mut offset)?;
+    let mut entries = Vec::with_capacity(count.min(bytes.len()));
+    for _ in 0..count {
+        let key_len = read_u32(&mut offset)?;
+        let key = std::str::from_utf8(bytes.get(offset..offset + key_len)?).ok()?;
+        offset += key_len;
+
+        let has_value = *bytes.get(offset)? != 0;
+        offset += 1;
+        let value = if has_value {
+            let len = read_u32(&mut offset)?;
+            let value = bytes.get(offset..offset + len)?.to_vec();
+            offset += len;
+            Some(value)
+        } else {
+            None
+        };
+
+        entries.push((key.to_string(), value));
+    }
+
+    Some(entries)
+}
+
+// プラグインと同じくファイル名を `title` にし，`--input` で上書きする
+// ? フロントマターやテーマは渡さない
+fn inputs(args: &Args, path: &Path) -> Dict {
+    let mut inputs = Dict::new();
+    if let Some(stem) = path.file_stem() {
+        inputs.insert("title".into(), stem.to_string_lossy().as_ref().into_value());
+    }
+    for (key, value) in &args.inputs {
+        inputs.insert(key.as_str().into(), value.as_str().into_value());
+    }
+
+    inputs
+}
+
+fn render_note(
+    compiler: &mut Compiler,
+    settings: &Settings,
+    args: &Args,
+    path: &Path,
+    rel: &Path,
+    report: &mut Report,
+) -> Result<(), String> {
+    let markdown = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+
+    let mut index = 0;
+    for segment in math_segments(&markdown) {
+        let Some(resolved) = settings.resolve(&segment) else {
+            continue;
+        };
+        index += 1;
+
+        let filled = settings.template(resolved.processor).fill(
+            &resolved.code,
+            resolved.kind == "display",
+            None,
+        );
+        let code = &filled.text;
+        let (line, column) = line_column(&markdown, segment.range.start);
+        let location = format!("{}:{line}:{column}", rel.display());
+        // ? 数式中の位置に戻す
+        let locate = |compiler: &Compiler, diag: &SourceDiagnostic| {
+            let located = locate(compiler, &segment, &resolved, &filled, diag.clone());
+            let (line, column) = line_column(&markdown, located.range.start);
+            let note = match located.origin {
+                Some(Origin::Preamble(_)) => Some("in the preamble"),
+                Some(Origin::Template) => Some("in the processor template"),
+                _ => None,
+            };
+
+            (format!("{}:{line}:{column}", rel.display()), note)
+        };
+
+        let result = match args.format {
+            Format::Pdf => compiler.pdf(
+                &format!("{}_{}.typ", resolved.kind, resolved.processor.id),
+                code,
+            ),
+            Format::Svg | Format::Png => compiler
+                .svg(code, resolved.kind, &resolved.processor.id)
+                .map(|Compiled { output, warnings }| Compiled {
+                    output: output.into_bytes(),
+                    warnings,
+                }),
+        };
+
+        let target = args.out.as_ref().map(|out| {
+            out.join(rel.with_extension(""))
+                .join(format!("{index}.{}", args.format.extension()))
+        });
+        emit(
+            compiler,
+            args,
+            result,
+            &location,
+            &locate,
+            target.as_deref(),
+            report,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn render_typ(
+    compiler: &mut Compiler,
+    args: &Args,
+    path: &Path,
+    rel: &Path,
+    report: &mut Report,
+) -> Result<(), String> {
+    let code = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let vpath = rel.to_string_lossy().replace('\\', "/");
+
+    let result = match args.format {
+        Format::Pdf => compiler.pdf(&vpath, &code),
+        Format::Svg | Format::Png => {
+            compiler
+                .document(&vpath, &code)
+                .and_then(|Compiled { output, warnings }| {
+                    if output.pages.is_empty() {
+                        return Err(CompileError::NoPages);
+                    }
+
+                    Ok(Compiled {
+                        output: typst_svg::svg_merged(&output, Abs::zero()).into_bytes(),
+                        warnings,
+                    })
+                })
+        }
+    };
+
+    let target = args
+        .out
+        .as_ref()
+        .map(|out| out.join(rel.with_extension(args.format.extension())));
+    let location = rel.display().to_string();
+    let locate = |compiler: &Compiler, diag: &SourceDiagnostic| {
+        let located = diag
+            .span
+            .id()
+            .filter(|id| *id == compiler.world().main())
+            .and_then(|id| span_location(compiler, id, diag));
+
+        (located.unwrap_or_else(|| location.clone()), None)
+    };
+    emit(
+        compiler,
+        args,
+        result,
+        &location,
+        &locate,
+        target.as_deref(),
+        report,
+    )
+}
+
+fn emit(
+    compiler: &Compiler,
+    args: &Args,
+    result: Result<Compiled<Vec<u8>>, CompileError>,
+    location: &str,
+    locate: &Locate,
+    target: Option<&Path>,
+    report: &mut Report,
+) -> Result<(), String> {
+    let Compiled { output, warnings } = match result {
+        Ok(compiled) => compiled,
+        Err(CompileError::Diagnostics(errors)) => {
+            for diag in &errors {
+                print_diagnostic(compiler, locate, diag);
+            }
+            report.errors += errors.len();
+            return Ok(());
+        }
+        Err(CompileError::NeedsFiles(ids)) => {
+            for id in ids {
+                eprintln!("{location}: error: file not found: {}", file_key(id));
+                report.errors += 1;
+            }
+            return Ok(());
+        }
+        Err(CompileError::NoPages) => {
+            eprintln!("{location}: error: document has no pages");
+            report.errors += 1;
+            return Ok(());
+        }
+    };
+
+    for diag in &warnings {
+        print_diagnostic(compiler, locate, diag);
+    }
+    report.warnings += warnings.len();
+
+    let Some(target) = target else {
+        report.rendered += 1;
+        return Ok(());
+    };
+
+    let bytes = match args.format {
+        Format::Pdf => output,
+        Format::Svg => {
+            let svg = String::from_utf8_lossy(&output);
+            match &args.base_color {
+                Some(color) => svg.replace(BASE_COLOR_VAR, color).into_bytes(),
+                None => output,
+            }
+        }
+        Format::Png => {
+            let svg = String::from_utf8_lossy(&output).replace(
+                BASE_COLOR_VAR,
+                args.base_color.as_deref().unwrap_or("#000000"),
+            );
+            match svg_to_png(&svg, args.scale) {
+                Ok(png) => png,
+                Err(message) => {
+                    eprintln!("{location}: error: {message}");
+                    report.errors += 1;
+                    return Ok(());
+                }
+            }
+        }
+    };
+
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("{}: {e}", parent.display()))?;
+    }
+    fs::write(target, bytes).map_err(|e| format!("{}: {e}", target.display()))?;
+    report.rendered += 1;
+
+    Ok(())
+}
+
+fn print_diagnostic(compiler: &Compiler, locate: &Locate, diag: &SourceDiagnostic) {
+    let severity = match diag.severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+    };
+    let (location, note) = locate(compiler, diag);
+
+    // ? メインファイル以外 (パッケージや import 先) は，その位置も添える
+    let origin = diag
+        .span
+        .id()
+        .filter(|id| *id != compiler.world().main())
+        .and_then(|id| span_location(compiler, id, diag));
+    match origin.as_deref().or(note) {
+        Some(origin) => eprintln!("{location}: {severity}: {} ({origin})", diag.message),
+        None => eprintln!("{location}: {severity}: {}", diag.message),
+    }
+    for hint in &diag.hints {
+        eprintln!("  hint: {hint}");
+    }
+}
+
+fn span_location(compiler: &Compiler, id: FileId, diag: &SourceDiagnostic) -> Option<String> {
+    let source = compiler.world().source(id).ok()?;
+    let range = source.range(diag.span)?;
+    let (line, column) = line_column(source.text(), range.start);
+
+    Some(format!("{}:{line}:{column}", file_key(id)))
+}
+
+// 1 始まりの行と列 (文字単位)
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+
+    (line, before[line_start..].chars().count() + 1)
+}
+
+fn svg_to_png(svg: &str, scale: f32) -> Result<Vec<u8>, String> {
+    use resvg::{tiny_skia, usvg};
+
+    let tree = usvg::Tree::from_str(svg, &usvg::Options::default()).map_err(|e| e.to_string())?;
+    let size = tree
+        .size()
+        .to_int_size()
+        .scale_by(scale)
+        .ok_or("image is empty")?;
+    let mut pixmap = tiny_skia::Pixmap::new(size.width(), size.height()).ok_or("image is empty")?;
+    resvg::render(
+        &tree,
+        tiny_skia::Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+
+    pixmap.encode_png().map_err(|e| e.to_string())
+}
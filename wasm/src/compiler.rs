@@ -0,0 +1,266 @@
+use rustc_hash::FxHashMap;
+use typst::{
+    World,
+    diag::{SourceDiagnostic, Warned},
+    ecow::EcoVec,
+    foundations::{Bytes, Dict},
+    layout::PagedDocument,
+    syntax::{FileId, VirtualPath},
+};
+use typst_pdf::PdfOptions;
+
+use crate::lexer::bracket::bracket_lexer;
+use crate::parser::bracket::{BracketPair, paren_parse};
+use crate::provider::FileProvider;
+use crate::template::Template;
+use crate::world::WasmWorld;
+
+pub struct Compiled<T> {
+    pub output: T,
+    pub warnings: EcoVec<SourceDiagnostic>,
+}
+
+#[derive(Debug)]
+pub enum CompileError {
+    Diagnostics(EcoVec<SourceDiagnostic>),
+    // 読み込み待ちのファイル。用意してから再実行する
+    NeedsFiles(Vec<FileId>),
+    NoPages,
+}
+
+// プラグインと同じ描画パイプライン
+pub struct Compiler {
+    pub(crate) world: WasmWorld,
+
+    last_kind: String,
+    last_id: String,
+
+    // (kind, id) ごとのプロセッサー
+    templates: FxHashMap<(String, String), Template>,
+}
+
+impl Compiler {
+    pub fn new(provider: Box<dyn FileProvider>, fontsize: f64) -> Self {
+        Self {
+            world: WasmWorld::new(provider, fontsize),
+
+            last_kind: String::new(),
+            last_id: String::new(),
+
+            templates: FxHashMap::default(),
+        }
+    }
+
+    pub fn world(&self) -> &WasmWorld {
+        &self.world
+    }
+
+    pub fn world_mut(&mut self) -> &mut WasmWorld {
+        &mut self.world
+    }
+
+    pub fn set_template(&mut self, kind: &str, id: &str, template: Template) {
+        self.templates
+            .insert((kind.to_string(), id.to_string()), template);
+    }
+
+    pub fn clear_templates(&mut self) {
+        self.templates.clear();
+    }
+
+    pub fn template(&self, kind: &str, id: &str) -> Option<&Template> {
+        self.templates.get(&(kind.to_string(), id.to_string()))
+    }
+
+    pub fn add_font(&mut self, data: Vec<u8>) {
+        self.world.add_font(Bytes::new(data));
+    }
+
+    // 以降のコンパイルの `sys.inputs`
+    pub fn set_inputs(&mut self, inputs: Dict) {
+        self.world.set_inputs(inputs);
+    }
+
+    fn update_source(&mut self, vpath: VirtualPath, code: &str) {
+        let file_id = FileId::new(None, vpath.clone());
+        let result = self.world.source(file_id);
+
+        match result {
+            Ok(_source) => {
+                self.world.set_main(file_id);
+                self.world.replace(code);
+            }
+            Err(_e) => {
+                self.world.add_file_text(vpath.clone(), code.into());
+                self.world.set_main(file_id);
+            }
+        }
+    }
+
+    fn compile(&self) -> Result<Compiled<PagedDocument>, CompileError> {
+        self.world.take_pending();
+        let Warned { output, warnings } = typst::compile::<PagedDocument>(&self.world);
+
+        let pending = self.world.take_pending();
+        if !pending.is_empty() {
+            return Err(CompileError::NeedsFiles(pending));
+        }
+
+        output
+            .map(|document| Compiled {
+                output: document,
+                warnings,
+            })
+            .map_err(CompileError::Diagnostics)
+    }
+
+    // プロセッサーごとのファイルをメインにしてコンパイルする
+    fn compile_processor(
+        &mut self,
+        code: &str,
+        kind: &str,
+        id: &str,
+    ) -> Result<Compiled<PagedDocument>, CompileError> {
+        if self.last_kind == kind && self.last_id == id {
+            self.world.replace(code);
+        } else {
+            self.last_kind = kind.to_string();
+            self.last_id = id.to_string();
+
+            self.update_source(VirtualPath::new(format!("{}_{}.typ", kind, id)), code);
+        }
+
+        let compiled = self.compile()?;
+        if compiled.output.pages.is_empty() {
+            return Err(CompileError::NoPages);
+        }
+
+        Ok(compiled)
+    }
+
+    // svg と同じだが，描画はしない
+    pub fn check(
+        &mut self,
+        code: &str,
+        kind: &str,
+        id: &str,
+    ) -> Result<Compiled<()>, CompileError> {
+        let Compiled { warnings, .. } = self.compile_processor(code, kind, id)?;
+
+        Ok(Compiled {
+            output: (),
+            warnings,
+        })
+    }
+
+    pub fn svg(
+        &mut self,
+        code: &str,
+        kind: &str,
+        id: &str,
+    ) -> Result<Compiled<String>, CompileError> {
+        let Compiled { output, warnings } = self.compile_processor(code, kind, id)?;
+
+        // ? typst_svg::svg は背景が透過しない
+        let svg = typst_svg::svg_frame(&output.pages[0].frame)
+            .replace("#000000", "var(--typst-base-color)")
+            .replacen("<svg class", "<svg style=\"overflow: visible;\" class", 1);
+
+        Ok(Compiled {
+            output: svg,
+            warnings,
+        })
+    }
+
+    // `path` をメインファイルとして文書全体をコンパイルする
+    pub fn document(
+        &mut self,
+        path: &str,
+        code: &str,
+    ) -> Result<Compiled<PagedDocument>, CompileError> {
+        // ? svg の差分コンパイルの対象から外す
+        self.last_kind.clear();
+        self.last_id.clear();
+
+        self.update_source(VirtualPath::new(path), code);
+        self.compile()
+    }
+
+    pub fn pdf(&mut self, filename: &str, code: &str) -> Result<Compiled<Vec<u8>>, CompileError> {
+        let Compiled {
+            output: mut document,
+            warnings,
+        } = self.document(filename, code)?;
+        document.info.title.get_or_insert_with(|| filename.into());
+        let options = PdfOptions::default();
+
+        let pdf = typst_pdf::pdf(&document, &options).map_err(CompileError::Diagnostics)?;
+
+        Ok(Compiled {
+            output: pdf,
+            warnings,
+        })
+    }
+}
+
+pub fn find_bracket_pairs(code: &str) -> Vec<BracketPair> {
+    let tokens = bracket_lexer(code);
+    paren_parse(&tokens)
+}
+
+pub fn mitex(code: &str) -> Result<String, String> {
+    mitex::convert_math(code, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use rustc_hash::FxHashMap;
+    use typst::{diag::FileResult, foundations::Bytes, syntax::package::PackageSpec};
+
+    use super::{CompileError, Compiled, Compiler};
+    use crate::provider::FileProvider;
+    use crate::utils::package::file_key;
+
+    // ホストが非同期に読み込むファイル。用意されるまでは読み込み中 (None) を返す
+    #[derive(Clone, Default)]
+    struct AsyncFileProvider {
+        files: Arc<Mutex<FxHashMap<String, Bytes>>>,
+    }
+
+    impl AsyncFileProvider {
+        fn supply(&self, key: &str, text: &str) {
+            let bytes = Bytes::new(text.as_bytes().to_vec());
+            self.files.lock().unwrap().insert(key.to_string(), bytes);
+        }
+    }
+
+    impl FileProvider for AsyncFileProvider {
+        fn fetch(&self, key: &str, _: Option<&PackageSpec>) -> Option<FileResult<Bytes>> {
+            self.files.lock().unwrap().get(key).cloned().map(Ok)
+        }
+    }
+
+    fn needs<T>(result: Result<Compiled<T>, CompileError>) -> Vec<String> {
+        match result {
+            Err(CompileError::NeedsFiles(ids)) => ids.into_iter().map(file_key).collect(),
+            Err(e) => panic!("unexpected error: {e:?}"),
+            Ok(_) => Vec::new(),
+        }
+    }
+
+    #[test]
+    fn needs_files_round_trip() {
+        let provider = AsyncFileProvider::default();
+        let mut compiler = Compiler::new(Box::new(provider.clone()), 16.0);
+        let code = "#import \"a.typ\": x\n$x$";
+
+        // ? import はファイルごとに止まるので，依存先は 1 つずつ分かる
+        assert_eq!(needs(compiler.svg(code, "inline", "test")), ["a.typ"]);
+        provider.supply("a.typ", "#import \"b.typ\": y\n#let x = y");
+        assert_eq!(needs(compiler.svg(code, "inline", "test")), ["b.typ"]);
+        provider.supply("b.typ", "#let y = 1");
+        assert!(compiler.svg(code, "inline", "test").is_ok());
+    }
+}
@@ -56,26 +56,26 @@ pub fn bracket_lexer(src: &str) -> Vec<BracketToken> {
                     chars.next();
                     in_block_comment -= 1;
                 }
-            } else if c == '/' {
-                if let Some((_, '*')) = chars.peek() {
-                    chars.next();
-                    in_block_comment += 1;
-                }
+            } else if c == '/'
+                && let Some((_, '*')) = chars.peek()
+            {
+                chars.next();
+                in_block_comment += 1;
             }
             continue;
         }
         // コメント開始
-        if c == '/' {
-            if let Some((_, next)) = chars.peek() {
-                if *next == '/' {
-                    chars.next();
-                    in_line_comment = true;
-                    continue;
-                } else if *next == '*' {
-                    chars.next();
-                    in_block_comment += 1;
-                    continue;
-                }
+        if c == '/'
+            && let Some((_, next)) = chars.peek()
+        {
+            if *next == '/' {
+                chars.next();
+                in_line_comment = true;
+                continue;
+            } else if *next == '*' {
+                chars.next();
+                in_block_comment += 1;
+                continue;
             }
         }
         // 文字列開始
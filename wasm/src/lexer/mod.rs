@@ -1 +1,2 @@
 pub mod bracket;
+pub mod snippet;
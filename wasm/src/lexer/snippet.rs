@@ -0,0 +1,208 @@
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnippetToken {
+    Text(String),
+    // `$1`, `${1}`, `${1:default}`
+    Tabstop {
+        index: usize,
+        default: Option<String>,
+    },
+    // 旧形式の `#CURSOR`
+    Cursor,
+}
+
+// ? `#CURSOR` を含む旧形式のスニペットは，`#CURSOR` 以外をそのまま挿入する
+// ? `\$` は Typst のエスケープなので，タブストップを文字通り書くには `$$1` や `$${1}` とする
+pub fn snippet_lexer(src: &str) -> Vec<SnippetToken> {
+    if src.contains("#CURSOR") {
+        return legacy_lexer(src);
+    }
+
+    let mut ret = Vec::new();
+    let mut text = String::new();
+    let mut rest = src;
+
+    while let Some(c) = rest.chars().next() {
+        // エスケープ
+        if let Some(after) = rest.strip_prefix("$$")
+            && tabstop(after).is_some()
+        {
+            text.push('$');
+            rest = after;
+            continue;
+        }
+        if c == '$'
+            && let Some((token, after)) = tabstop(&rest[1..])
+        {
+            flush(&mut ret, &mut text);
+            ret.push(token);
+            rest = after;
+            continue;
+        }
+
+        text.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    flush(&mut ret, &mut text);
+
+    ret
+}
+
+fn legacy_lexer(src: &str) -> Vec<SnippetToken> {
+    let mut ret = Vec::new();
+    for (i, part) in src.split("#CURSOR").enumerate() {
+        if i != 0 {
+            ret.push(SnippetToken::Cursor);
+        }
+        if !part.is_empty() {
+            ret.push(SnippetToken::Text(part.to_string()));
+        }
+    }
+
+    ret
+}
+
+fn flush(ret: &mut Vec<SnippetToken>, text: &mut String) {
+    if !text.is_empty() {
+        ret.push(SnippetToken::Text(std::mem::take(text)));
+    }
+}
+
+// `$` の直後から読む
+fn tabstop(src: &str) -> Option<(SnippetToken, &str)> {
+    let digits = |s: &str| s.chars().take_while(char::is_ascii_digit).count();
+
+    // `$1`
+    let n = digits(src);
+    if n > 0 {
+        let index = src[..n].parse().ok()?;
+        return Some((
+            SnippetToken::Tabstop {
+                index,
+                default: None,
+            },
+            &src[n..],
+        ));
+    }
+
+    // `${1}`, `${1:default}`
+    let inner = src.strip_prefix('{')?;
+    let n = digits(inner);
+    if n == 0 {
+        return None;
+    }
+    let index = inner[..n].parse().ok()?;
+    let after = &inner[n..];
+    if let Some(after) = after.strip_prefix('}') {
+        return Some((
+            SnippetToken::Tabstop {
+                index,
+                default: None,
+            },
+            after,
+        ));
+    }
+
+    // ? 既定値には Typst のコードブロックが入りうるので，波括弧の対応を取る
+    // ? `\}` などは Typst のエスケープとしてそのまま残し，対応には数えない
+    // ? `${1:"}"}` のように文字列リテラル内の波括弧も数えない
+    let body = after.strip_prefix(':')?;
+    let mut depth = 0usize;
+    let mut i = 0;
+    while let Some(c) = body[i..].chars().next() {
+        match c {
+            '\\' => {
+                i += 1 + body[i + 1..].chars().next().map_or(0, char::len_utf8);
+                continue;
+            }
+            '"' => {
+                if let Some(n) = string_len(&body[i..]) {
+                    i += n;
+                    continue;
+                }
+            }
+            '{' => depth += 1,
+            '}' if depth == 0 => {
+                return Some((
+                    SnippetToken::Tabstop {
+                        index,
+                        default: Some(body[..i].to_string()),
+                    },
+                    &body[i + 1..],
+                ));
+            }
+            '}' => depth -= 1,
+            _ => {}
+        }
+        i += c.len_utf8();
+    }
+
+    // 閉じられていない
+    None
+}
+
+// `"` から始まる文字列リテラルの長さ
+// ? 閉じられていなければ，`"` は通常の文字として扱う
+fn string_len(src: &str) -> Option<usize> {
+    let mut chars = src.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '"' => return Some(i + 1),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::snippet::{expand, substitute_captures};
+
+    fn text(src: &str) -> String {
+        expand(&snippet_lexer(src)).text
+    }
+
+    #[test]
+    fn legacy_snippets_keep_typst_escapes() {
+        assert_eq!(
+            snippet_lexer("\\$x$1 #CURSOR"),
+            vec![
+                SnippetToken::Text("\\$x$1 ".to_string()),
+                SnippetToken::Cursor,
+            ]
+        );
+    }
+
+    #[test]
+    fn tabstops_and_escapes() {
+        assert_eq!(text("\\$ $1 ${2:a} $$1 $${2}"), "\\$  a $1 ${2}");
+        assert_eq!(text("$ x $"), "$ x $");
+        assert_eq!(text("${1:#{ \\} }} $1"), "#{ \\} } #{ \\} }");
+    }
+
+    #[test]
+    fn captures_are_not_tabstops() {
+        let tokens = substitute_captures(snippet_lexer("[[0]]$1"), &["$2".to_string()]);
+        let expansion = expand(&tokens);
+        assert_eq!(expansion.text, "$2");
+        assert_eq!(expansion.tabstops.len(), 1);
+    }
+
+    #[test]
+    fn nested_placeholders() {
+        assert_eq!(text("${1:\"}\"} $1"), "\"}\" \"}\"");
+        assert_eq!(text("${1:#{ \"a\\\"}\" }}"), "#{ \"a\\\"}\" }");
+        assert_eq!(text("${1:#{ x }} ${2:\"}"), "#{ x } \"");
+        assert_eq!(
+            snippet_lexer("${1:(${2:x})}"),
+            vec![SnippetToken::Tabstop {
+                index: 1,
+                default: Some("(${2:x})".to_string()),
+            }]
+        );
+    }
+}
@@ -1 +1,3 @@
 pub mod bracket;
+pub mod snippet;
+pub mod syntax;
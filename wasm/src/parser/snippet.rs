@@ -0,0 +1,171 @@
+use std::ops::Range;
+
+use regex::Regex;
+use rustc_hash::FxHashMap;
+
+use crate::lexer::snippet::SnippetToken;
+
+#[derive(Debug, Clone)]
+pub struct Tabstop {
+    // `#CURSOR` は番号を持たない
+    pub index: Option<usize>,
+    pub ranges: Vec<Range<usize>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Expansion {
+    pub text: String,
+    // 移動順に並んだタブストップ
+    pub tabstops: Vec<Tabstop>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TriggerMatch {
+    pub start: usize,
+    pub captures: Vec<String>,
+}
+
+// ? 正規表現のトリガーは毎回コンパイルしないよう，一度だけコンパイルして使い回す
+#[derive(Debug, Clone)]
+pub enum Trigger {
+    Literal(String),
+    Regex(Regex),
+}
+
+impl Trigger {
+    pub fn new(trigger: &str, regex: bool) -> Result<Self, regex::Error> {
+        if !regex {
+            return Ok(Self::Literal(trigger.to_string()));
+        }
+
+        Ok(Self::Regex(Regex::new(&format!("(?:{})$", trigger))?))
+    }
+
+    // カーソル直前のテキストがトリガーで終わっていれば，置き換え開始位置を返す
+    pub fn matches(&self, before: &str) -> Option<TriggerMatch> {
+        match self {
+            Self::Literal(trigger) => {
+                before
+                    .strip_suffix(trigger.as_str())
+                    .map(|rest| TriggerMatch {
+                        start: rest.len(),
+                        captures: Vec::new(),
+                    })
+            }
+            Self::Regex(re) => re.captures(before).map(|caps| TriggerMatch {
+                start: caps.get(0).map_or(before.len(), |m| m.start()),
+                captures: caps
+                    .iter()
+                    .skip(1)
+                    .map(|m| m.map_or(String::new(), |m| m.as_str().to_string()))
+                    .collect(),
+            }),
+        }
+    }
+}
+
+// `[[0]]` を 1 番目のキャプチャで置き換える (Obsidian LaTeX Suite 互換)
+// ? キャプチャ内の `$` をタブストップとして解釈させないため，字句解析の後に置き換える
+pub fn substitute_captures(tokens: Vec<SnippetToken>, captures: &[String]) -> Vec<SnippetToken> {
+    let substitute = |text: String| {
+        captures
+            .iter()
+            .enumerate()
+            .fold(text, |text, (i, capture)| {
+                text.replace(&format!("[[{}]]", i), capture)
+            })
+    };
+
+    tokens
+        .into_iter()
+        .map(|token| match token {
+            SnippetToken::Text(t) => SnippetToken::Text(substitute(t)),
+            SnippetToken::Tabstop { index, default } => SnippetToken::Tabstop {
+                index,
+                default: default.map(substitute),
+            },
+            SnippetToken::Cursor => SnippetToken::Cursor,
+        })
+        .collect()
+}
+
+pub fn expand(tokens: &[SnippetToken]) -> Expansion {
+    // ミラーのため，番号ごとの既定値を先に集める
+    let mut defaults: FxHashMap<usize, &str> = FxHashMap::default();
+    for token in tokens {
+        if let SnippetToken::Tabstop {
+            index,
+            default: Some(default),
+        } = token
+        {
+            defaults.entry(*index).or_insert(default);
+        }
+    }
+
+    let mut text = String::new();
+    let mut numbered: FxHashMap<usize, Vec<Range<usize>>> = FxHashMap::default();
+    let mut cursors = Vec::new();
+    for token in tokens {
+        match token {
+            SnippetToken::Text(t) => text.push_str(t),
+            SnippetToken::Tabstop { index, .. } => {
+                let start = text.len();
+                text.push_str(defaults.get(index).copied().unwrap_or_default());
+                numbered.entry(*index).or_default().push(start..text.len());
+            }
+            SnippetToken::Cursor => cursors.push(text.len()..text.len()),
+        }
+    }
+
+    // $1, $2, ..., #CURSOR, $0 の順
+    let mut indices: Vec<usize> = numbered.keys().copied().filter(|i| *i != 0).collect();
+    indices.sort_unstable();
+
+    let mut tabstops: Vec<Tabstop> = indices
+        .into_iter()
+        .map(|i| Tabstop {
+            index: Some(i),
+            ranges: numbered.remove(&i).unwrap_or_default(),
+        })
+        .collect();
+    tabstops.extend(cursors.into_iter().map(|r| Tabstop {
+        index: None,
+        ranges: vec![r],
+    }));
+    if let Some(ranges) = numbered.remove(&0) {
+        tabstops.push(Tabstop {
+            index: Some(0),
+            ranges,
+        });
+    }
+
+    Expansion { text, tabstops }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn literal_trigger() {
+        let trigger = Trigger::new("mat", false).unwrap();
+        assert_eq!(trigger.matches("x + mat").unwrap().start, 4);
+        assert!(trigger.matches("x + ma").is_none());
+    }
+
+    #[test]
+    fn regex_trigger() {
+        let trigger = Trigger::new("([a-z])(\\d)", true).unwrap();
+        let matched = trigger.matches("1 + x2").unwrap();
+        assert_eq!(matched.start, 4);
+        assert_eq!(matched.captures, vec!["x".to_string(), "2".to_string()]);
+        assert!(trigger.matches("x2 + 1").is_none());
+
+        // 選択肢はまとめて末尾に固定される
+        let trigger = Trigger::new("ab|cd", true).unwrap();
+        assert!(trigger.matches("ab + 1").is_none());
+        assert_eq!(trigger.matches("1 + cd").unwrap().start, 4);
+
+        assert!(Trigger::new("(", true).is_err());
+    }
+}
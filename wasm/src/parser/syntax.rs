@@ -0,0 +1,18 @@
+use typst::syntax::{SyntaxMode, SyntaxNode, parse, parse_code, parse_math};
+
+pub fn parse_mode(mode: &str) -> Option<SyntaxMode> {
+    match mode {
+        "markup" => Some(SyntaxMode::Markup),
+        "math" => Some(SyntaxMode::Math),
+        "code" => Some(SyntaxMode::Code),
+        _ => None,
+    }
+}
+
+pub fn parse_by_mode(code: &str, mode: SyntaxMode) -> SyntaxNode {
+    match mode {
+        SyntaxMode::Markup => parse(code),
+        SyntaxMode::Math => parse_math(code),
+        SyntaxMode::Code => parse_code(code),
+    }
+}
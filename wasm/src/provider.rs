@@ -0,0 +1,157 @@
+use std::path::PathBuf;
+
+use rustc_hash::FxHashMap;
+#[cfg(feature = "wasm")]
+use send_wrapper::SendWrapper;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::{JsCast, JsValue};
+
+use typst::{
+    diag::{FileError, FileResult, PackageError},
+    foundations::Bytes,
+    syntax::package::PackageSpec,
+};
+
+#[cfg(feature = "wasm")]
+use crate::serde::fetch::{FetchErrorDes, FetchErrorKind};
+
+// WasmWorld がスロットにないファイルを読むときに使う
+// key は `@namespace/name/version/path` またはボールト内のパス
+pub trait FileProvider: Send + Sync {
+    // None は非同期に読み込み中であることを表す
+    fn fetch(&self, key: &str, spec: Option<&PackageSpec>) -> Option<FileResult<Bytes>>;
+}
+
+// JS の fetch コールバック
+#[cfg(feature = "wasm")]
+pub struct JsFileProvider {
+    read: SendWrapper<js_sys::Function>,
+}
+
+#[cfg(feature = "wasm")]
+impl JsFileProvider {
+    pub fn new(read: js_sys::Function) -> Self {
+        Self {
+            read: SendWrapper::new(read),
+        }
+    }
+}
+
+#[cfg(feature = "wasm")]
+impl FileProvider for JsFileProvider {
+    fn fetch(&self, key: &str, spec: Option<&PackageSpec>) -> Option<FileResult<Bytes>> {
+        let js_value = match self.read.call1(&JsValue::NULL, &key.into()) {
+            Ok(js_value) => js_value,
+            Err(e) => {
+                return match serde_wasm_bindgen::from_value::<FetchErrorDes>(e.clone()) {
+                    Ok(des) if des.kind == FetchErrorKind::Pending => None,
+                    Ok(des) => Some(Err(des.into_file_error(key, spec))),
+                    // ? 構造化されていない例外 (文字列や Error オブジェクト)
+                    Err(_) => Some(Err(FileError::Other(
+                        e.as_string()
+                            .or_else(|| {
+                                e.dyn_ref::<js_sys::Error>()
+                                    .map(|e| String::from(e.message()))
+                            })
+                            .map(Into::into),
+                    ))),
+                };
+            }
+        };
+
+        if let Some(u8arr) = js_value.dyn_ref::<js_sys::Uint8Array>() {
+            Some(Ok(Bytes::new(u8arr.to_vec())))
+        } else {
+            Some(Err(FileError::Other(Some(
+                format!("fetch returned a non-Uint8Array value for {}", key).into(),
+            ))))
+        }
+    }
+}
+
+// テストやツール向けの，メモリ上のファイル
+#[derive(Default)]
+pub struct MemoryFileProvider {
+    files: FxHashMap<String, Bytes>,
+}
+
+impl MemoryFileProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, key: impl Into<String>, bytes: impl Into<Bytes>) {
+        self.files.insert(key.into(), bytes.into());
+    }
+}
+
+impl FileProvider for MemoryFileProvider {
+    fn fetch(&self, key: &str, spec: Option<&PackageSpec>) -> Option<FileResult<Bytes>> {
+        Some(self.files.get(key).cloned().ok_or_else(|| {
+            match spec {
+                Some(spec)
+                    if !self
+                        .files
+                        .keys()
+                        .any(|k| k.starts_with(&package_prefix(spec))) =>
+                {
+                    FileError::Package(PackageError::NotFound(spec.clone()))
+                }
+                _ => FileError::NotFound(PathBuf::from(key)),
+            }
+        }))
+    }
+}
+
+// ネイティブのファイルシステム
+// パッケージは `{dir}/{namespace}/{name}/{version}/` から探す
+pub struct FsFileProvider {
+    root: PathBuf,
+    package_dirs: Vec<PathBuf>,
+}
+
+impl FsFileProvider {
+    pub fn new(root: impl Into<PathBuf>, package_dirs: Vec<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            package_dirs,
+        }
+    }
+}
+
+impl FileProvider for FsFileProvider {
+    fn fetch(&self, key: &str, spec: Option<&PackageSpec>) -> Option<FileResult<Bytes>> {
+        let Some(spec) = spec else {
+            let path = self.root.join(key);
+            return Some(
+                std::fs::read(&path)
+                    .map(Bytes::new)
+                    .map_err(|e| FileError::from_io(e, &path)),
+            );
+        };
+
+        let rest = key.strip_prefix(&package_prefix(spec)).unwrap_or(key);
+        for dir in &self.package_dirs {
+            let package_dir = dir
+                .join(spec.namespace.as_str())
+                .join(spec.name.as_str())
+                .join(spec.version.to_string());
+            if package_dir.is_dir() {
+                let path = package_dir.join(rest);
+                return Some(
+                    std::fs::read(&path)
+                        .map(Bytes::new)
+                        .map_err(|e| FileError::from_io(e, &path)),
+                );
+            }
+        }
+
+        Some(Err(FileError::Package(PackageError::NotFound(
+            spec.clone(),
+        ))))
+    }
+}
+
+fn package_prefix(spec: &PackageSpec) -> String {
+    format!("@{}/{}/{}/", spec.namespace, spec.name, spec.version)
+}
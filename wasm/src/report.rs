@@ -0,0 +1,277 @@
+use std::io::{self, Write};
+use std::ops::Range;
+
+use rustc_hash::FxHashSet;
+
+use codespan_reporting::{
+    diagnostic::{Diagnostic, Label, LabelStyle, Severity as ReportSeverity},
+    files::{Error, Files},
+    term::{self, Config, GeneralWriteResult, WriteStyle},
+};
+use typst::{
+    World, WorldExt,
+    diag::{Severity, SourceDiagnostic},
+    syntax::{FileId, Source, Span},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Text,
+    // `typstmate-diag-*` のクラスを付けた <pre> の中身
+    Html,
+}
+
+// 1 つのファイルの診断。`id` が None なら位置のない診断
+pub struct FileDiagnostics {
+    pub id: Option<FileId>,
+    pub diagnostics: Vec<SourceDiagnostic>,
+}
+
+// 同じ重大度・位置・メッセージの診断を 1 つにまとめ，ファイルごとに分ける
+// `severity` を指定するとその重大度のものだけを残す
+// ? メインファイル，他のファイル (パッケージ・パス順)，位置のないものの順に並べ，ファイル内では位置順
+pub fn group_by_file<W>(
+    world: &W,
+    diagnostics: &[SourceDiagnostic],
+    severity: Option<Severity>,
+) -> Vec<FileDiagnostics>
+where
+    W: World,
+{
+    let mut seen = FxHashSet::default();
+    let mut groups: Vec<FileDiagnostics> = Vec::new();
+
+    for diag in diagnostics {
+        if severity.is_some_and(|severity| severity != diag.severity)
+            || !seen.insert((diag.severity, diag.span, diag.message.clone()))
+        {
+            continue;
+        }
+
+        let id = diag.span.id();
+        match groups.iter_mut().find(|group| group.id == id) {
+            Some(group) => group.diagnostics.push(diag.clone()),
+            None => groups.push(FileDiagnostics {
+                id,
+                diagnostics: vec![diag.clone()],
+            }),
+        }
+    }
+
+    let main = world.main();
+    groups.sort_by_cached_key(|group| match group.id {
+        Some(id) if id == main => (0, None, String::new()),
+        Some(id) => (
+            1,
+            id.package().map(|spec| spec.to_string()),
+            id.vpath().as_rootless_path().to_string_lossy().into_owned(),
+        ),
+        None => (2, None, String::new()),
+    });
+    for group in &mut groups {
+        group
+            .diagnostics
+            .sort_by_cached_key(|diag| world.range(diag.span).map(|range| range.start));
+    }
+
+    groups
+}
+
+// Typst CLI と同じ形式 (ソースの抜粋・下線・トレース・ヒント) で診断を書き出す
+pub fn report<W>(world: &W, diagnostics: &[SourceDiagnostic], format: ReportFormat) -> String
+where
+    W: World,
+{
+    let files = WorldFiles(world);
+    let config = Config {
+        tab_width: 2,
+        ..Default::default()
+    };
+
+    let mut text = String::new();
+    let mut html = HtmlWriter::default();
+    for diag in diagnostics {
+        let primary = match diag.severity {
+            Severity::Error => Diagnostic::error(),
+            Severity::Warning => Diagnostic::warning(),
+        }
+        .with_message(diag.message.as_str())
+        .with_notes(
+            diag.hints
+                .iter()
+                .map(|hint| format!("hint: {hint}"))
+                .collect(),
+        )
+        .with_labels(label(world, diag.span).into_iter().collect());
+
+        // ? トレースは CLI と同じく help として続ける
+        let trace = diag.trace.iter().map(|point| {
+            Diagnostic::help()
+                .with_message(point.v.to_string())
+                .with_labels(label(world, point.span).into_iter().collect())
+        });
+
+        for diagnostic in std::iter::once(primary).chain(trace) {
+            // ? 書き出しに失敗した診断は飛ばす
+            let _ = match format {
+                ReportFormat::Text => term::emit_to_string(&mut text, &config, &files, &diagnostic),
+                ReportFormat::Html => {
+                    term::emit_to_write_style(&mut html, &config, &files, &diagnostic)
+                }
+            };
+        }
+    }
+
+    match format {
+        ReportFormat::Text => text,
+        ReportFormat::Html => html.finish(),
+    }
+}
+
+fn label<W>(world: &W, span: Span) -> Option<Label<FileId>>
+where
+    W: World,
+{
+    Some(Label::primary(span.id()?, world.range(span)?))
+}
+
+// codespan-reporting から World のファイルを読む
+struct WorldFiles<'a, W>(&'a W);
+
+impl<'a, W> WorldFiles<'a, W>
+where
+    W: World,
+{
+    fn lookup(&self, id: FileId) -> Result<Source, Error> {
+        self.0.source(id).map_err(|_| Error::FileMissing)
+    }
+}
+
+impl<'a, W> Files<'a> for WorldFiles<'a, W>
+where
+    W: World,
+{
+    type FileId = FileId;
+    type Name = String;
+    type Source = Source;
+
+    fn name(&'a self, id: FileId) -> Result<String, Error> {
+        let path = id.vpath().as_rootless_path().to_string_lossy().into_owned();
+        Ok(match id.package() {
+            Some(spec) => format!("{spec}/{path}"),
+            None => path,
+        })
+    }
+
+    fn source(&'a self, id: FileId) -> Result<Source, Error> {
+        self.lookup(id)
+    }
+
+    fn line_index(&'a self, id: FileId, byte_index: usize) -> Result<usize, Error> {
+        let source = self.lookup(id)?;
+        source
+            .lines()
+            .byte_to_line(byte_index)
+            .ok_or_else(|| Error::IndexTooLarge {
+                given: byte_index,
+                max: source.lines().len_bytes(),
+            })
+    }
+
+    fn line_range(&'a self, id: FileId, line_index: usize) -> Result<Range<usize>, Error> {
+        let source = self.lookup(id)?;
+        source
+            .lines()
+            .line_to_range(line_index)
+            .ok_or_else(|| Error::LineTooLarge {
+                given: line_index,
+                max: source.lines().len_lines(),
+            })
+    }
+}
+
+// スタイルを <span class="typstmate-diag-*"> にし，テキストをエスケープする
+#[derive(Default)]
+struct HtmlWriter {
+    html: Vec<u8>,
+    open: bool,
+}
+
+impl HtmlWriter {
+    fn finish(mut self) -> String {
+        let _ = self.reset();
+        String::from_utf8_lossy(&self.html).into_owned()
+    }
+
+    fn open(&mut self, class: &str) -> GeneralWriteResult {
+        self.reset()?;
+        write!(self.html, "<span class=\"typstmate-diag-{class}\">")?;
+        self.open = true;
+        Ok(())
+    }
+}
+
+impl Write for HtmlWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            match byte {
+                b'&' => self.html.extend_from_slice(b"&amp;"),
+                b'<' => self.html.extend_from_slice(b"&lt;"),
+                b'>' => self.html.extend_from_slice(b"&gt;"),
+                b'"' => self.html.extend_from_slice(b"&quot;"),
+                _ => self.html.push(byte),
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn severity_class(severity: ReportSeverity) -> &'static str {
+    match severity {
+        ReportSeverity::Bug | ReportSeverity::Error => "error",
+        ReportSeverity::Warning => "warning",
+        ReportSeverity::Note => "note",
+        ReportSeverity::Help => "help",
+    }
+}
+
+impl WriteStyle for HtmlWriter {
+    fn set_header(&mut self, severity: ReportSeverity) -> GeneralWriteResult {
+        self.open(severity_class(severity))
+    }
+
+    fn set_header_message(&mut self) -> GeneralWriteResult {
+        self.open("message")
+    }
+
+    fn set_line_number(&mut self) -> GeneralWriteResult {
+        self.open("line-number")
+    }
+
+    fn set_note_bullet(&mut self) -> GeneralWriteResult {
+        self.open("note")
+    }
+
+    fn set_source_border(&mut self) -> GeneralWriteResult {
+        self.open("border")
+    }
+
+    fn set_label(&mut self, severity: ReportSeverity, style: LabelStyle) -> GeneralWriteResult {
+        match style {
+            LabelStyle::Primary => self.open(severity_class(severity)),
+            LabelStyle::Secondary => self.open("secondary"),
+        }
+    }
+
+    fn reset(&mut self) -> GeneralWriteResult {
+        if self.open {
+            self.html.extend_from_slice(b"</span>");
+            self.open = false;
+        }
+        Ok(())
+    }
+}
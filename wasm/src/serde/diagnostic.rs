@@ -2,27 +2,165 @@ use std::ops::Range;
 
 use serde::Serialize;
 
-use typst::diag::{Severity, SourceDiagnostic};
+use typst::{
+    World, WorldExt,
+    diag::{Severity, SourceDiagnostic},
+    syntax::Span,
+};
 
-#[derive(Serialize)]
+use crate::analyzer::fixes::{Fix, main_fixes};
+use crate::report::{ReportFormat, report};
+use crate::template::{Filled, Origin};
+use crate::utils::char_position::{char_position_at, utf8_to_utf16_offset};
+
+// 0 始まりの行と，UTF-16 の列・オフセット
+#[derive(Serialize, Clone)]
+pub struct PositionSer {
+    line: usize,
+    column: usize,
+    offset: usize,
+}
+
+#[derive(Serialize, Clone)]
+pub struct LocationSer {
+    // パッケージ内のファイルならパッケージからの相対パス
+    path: String,
+    // `@namespace/name:version`
+    package: Option<String>,
+    start: PositionSer,
+    end: PositionSer,
+}
+
+// 数式・前文中の UTF-16 の範囲
+#[derive(Serialize, Clone)]
+pub struct OriginSer {
+    kind: &'static str,
+    start: usize,
+    end: usize,
+}
+
+// UTF-16 の範囲 `from..to` を `text` に置き換える
+#[derive(Serialize, Clone)]
+pub struct TextEditSer {
+    from: usize,
+    to: usize,
+    text: String,
+}
+
+#[derive(Serialize, Clone)]
+pub struct FixSer {
+    title: String,
+    edits: Vec<TextEditSer>,
+}
+
+// Typst CLI と同じ形式の書き出し
+// ? 描画のたびに作ると重いので，診断を表示するときに別に求める
+#[derive(Serialize, Clone)]
+pub struct ReportSer {
+    text: String,
+    html: String,
+}
+
+#[derive(Serialize, Clone)]
 pub struct TraceSer {
     span: Range<usize>,
+    location: Option<LocationSer>,
     point: String,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 pub struct SourceDiagnosticSer {
     pub severity: u8,
     pub span: Range<usize>,
+    pub location: Option<LocationSer>,
+    pub origin: Option<OriginSer>,
     pub message: String,
     pub trace: Vec<TraceSer>,
     pub hints: Vec<String>,
+    // テンプレートに埋め込んだ数式に対する修正
+    pub fixes: Vec<FixSer>,
+}
+
+impl LocationSer {
+    pub fn from_span<W>(span: Span, world: &W) -> Option<Self>
+    where
+        W: World,
+    {
+        let id = span.id()?;
+        let source = world.source(id).ok()?;
+        let range = source.range(span)?;
+        let position = |offset| {
+            let p = char_position_at(source.text(), offset);
+            PositionSer {
+                line: p.line,
+                column: p.ch,
+                offset: p.offset,
+            }
+        };
+
+        Some(LocationSer {
+            path: id.vpath().as_rootless_path().to_string_lossy().into_owned(),
+            package: id.package().map(|spec| spec.to_string()),
+            start: position(range.start),
+            end: position(range.end),
+        })
+    }
+}
+
+impl OriginSer {
+    pub fn new(origin: &Origin, filled: &Filled) -> Self {
+        let utf16 = |text: &str, range: &Range<usize>| {
+            (
+                utf8_to_utf16_offset(text, range.start),
+                utf8_to_utf16_offset(text, range.end),
+            )
+        };
+
+        let (kind, (start, end)) = match origin {
+            Origin::Snippet(range) => ("snippet", utf16(filled.code(), range)),
+            Origin::Preamble(range) => ("preamble", utf16(filled.preamble().unwrap_or(""), range)),
+            Origin::Template => ("template", (0, 0)),
+        };
+
+        OriginSer { kind, start, end }
+    }
+}
+
+impl ReportSer {
+    pub fn new<W>(diag: &SourceDiagnostic, world: &W) -> Self
+    where
+        W: World,
+    {
+        let diags = std::slice::from_ref(diag);
+        ReportSer {
+            text: report(world, diags, ReportFormat::Text),
+            html: report(world, diags, ReportFormat::Html),
+        }
+    }
+}
+
+impl FixSer {
+    // `text` 中のバイト範囲の修正を UTF-16 に変換する
+    pub fn new(fix: &Fix, text: &str) -> Self {
+        FixSer {
+            title: fix.title.clone(),
+            edits: fix
+                .edits
+                .iter()
+                .map(|edit| TextEditSer {
+                    from: utf8_to_utf16_offset(text, edit.range.start),
+                    to: utf8_to_utf16_offset(text, edit.range.end),
+                    text: edit.text.clone(),
+                })
+                .collect(),
+        }
+    }
 }
 
 impl SourceDiagnosticSer {
     pub fn from_diag<W>(diag: &SourceDiagnostic, world: &W) -> Self
     where
-        W: typst::WorldExt,
+        W: World,
     {
         SourceDiagnosticSer {
             severity: match diag.severity {
@@ -30,16 +168,43 @@ impl SourceDiagnosticSer {
                 Severity::Warning => 2,
             },
             span: world.range(diag.span).unwrap_or(Range { start: 0, end: 0 }),
+            location: LocationSer::from_span(diag.span, world),
+            origin: None,
             message: diag.message.as_str().to_string(),
             trace: diag
                 .trace
                 .iter()
                 .map(|t| TraceSer {
                     span: world.range(t.span).unwrap_or(Range { start: 0, end: 0 }),
+                    location: LocationSer::from_span(t.span, world),
                     point: t.v.to_string(),
                 })
                 .collect(),
             hints: diag.hints.iter().map(|h| h.as_str().to_string()).collect(),
+            fixes: Vec::new(),
         }
     }
+
+    // テンプレートに埋め込んだ数式の診断。メインファイル中の位置を数式・前文の位置に戻す
+    // 修正はこちらでだけ求める
+    pub fn from_filled_diag<W>(diag: &SourceDiagnostic, world: &W, filled: &Filled) -> Self
+    where
+        W: World,
+    {
+        let mut ser = Self::from_diag(diag, world);
+        if diag.span.id() == Some(world.main())
+            && let Some(range) = world.range(diag.span)
+        {
+            ser.origin = Some(OriginSer::new(&filled.origin(range), filled));
+        }
+
+        // ? 数式の外を書き換える修正は捨てる
+        ser.fixes = main_fixes(diag, world)
+            .iter()
+            .filter_map(|fix| filled.fix(fix))
+            .map(|fix| FixSer::new(&fix, filled.code()))
+            .collect();
+
+        ser
+    }
 }
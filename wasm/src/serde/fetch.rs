@@ -0,0 +1,90 @@
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+use typst::{
+    diag::{FileError, PackageError},
+    syntax::package::{PackageSpec, PackageVersion},
+};
+
+use crate::utils::package::parse_package_spec;
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FetchErrorKind {
+    // JS 側で非同期に読み込み中
+    Pending,
+
+    NotFound,
+    AccessDenied,
+    IsDirectory,
+    NotSource,
+    InvalidUtf8,
+
+    PackageNotFound,
+    VersionNotFound,
+    NetworkFailed,
+    MalformedArchive,
+    PackageOther,
+
+    Other,
+}
+
+// fetch コールバックが投げるエラー
+#[derive(Deserialize)]
+pub struct FetchErrorDes {
+    pub kind: FetchErrorKind,
+    pub path: Option<String>,
+    pub message: Option<String>,
+    // `@namespace/name/version`。省略時は要求したパッケージ
+    pub package: Option<String>,
+    // version_not_found のときの最新バージョン
+    pub latest: Option<String>,
+}
+
+impl FetchErrorDes {
+    pub fn into_file_error(self, rpath: &str, spec: Option<&PackageSpec>) -> FileError {
+        let message = self.message.map(Into::into);
+        let spec = match &self.package {
+            Some(p) => parse_package_spec(p).ok(),
+            None => spec.cloned(),
+        };
+
+        match self.kind {
+            FetchErrorKind::NotFound => {
+                FileError::NotFound(PathBuf::from(self.path.as_deref().unwrap_or(rpath)))
+            }
+            FetchErrorKind::AccessDenied => FileError::AccessDenied,
+            FetchErrorKind::IsDirectory => FileError::IsDirectory,
+            FetchErrorKind::NotSource => FileError::NotSource,
+            FetchErrorKind::InvalidUtf8 => FileError::InvalidUtf8,
+
+            FetchErrorKind::PackageNotFound => match spec {
+                Some(spec) => FileError::Package(PackageError::NotFound(spec)),
+                None => FileError::Package(PackageError::Other(message)),
+            },
+            FetchErrorKind::VersionNotFound => {
+                let latest = self
+                    .latest
+                    .as_deref()
+                    .and_then(|v| v.parse::<PackageVersion>().ok());
+                match (spec, latest) {
+                    (Some(spec), Some(latest)) => {
+                        FileError::Package(PackageError::VersionNotFound(spec, latest))
+                    }
+                    (Some(spec), None) => FileError::Package(PackageError::NotFound(spec)),
+                    _ => FileError::Package(PackageError::Other(message)),
+                }
+            }
+            FetchErrorKind::NetworkFailed => {
+                FileError::Package(PackageError::NetworkFailed(message))
+            }
+            FetchErrorKind::MalformedArchive => {
+                FileError::Package(PackageError::MalformedArchive(message))
+            }
+            FetchErrorKind::PackageOther => FileError::Package(PackageError::Other(message)),
+
+            FetchErrorKind::Pending | FetchErrorKind::Other => FileError::Other(message),
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+
+use crate::serde::diagnostic::{FixSer, SourceDiagnosticSer};
+use crate::template::Origin;
+use crate::utils::char_position::char_position_at;
+use crate::vault::lint::{LintDiagnostic, NoteReport};
+use crate::world::WasmWorld;
+
+#[derive(Deserialize)]
+pub struct NoteDes {
+    pub path: String,
+    pub markdown: String,
+}
+
+// 位置は Obsidian の EditorPosition と同じく 0 始まりの行と UTF-16 の列
+#[derive(Serialize)]
+pub struct LintDiagnosticSer {
+    kind: &'static str,
+    id: String,
+    line: usize,
+    ch: usize,
+    from: usize,
+    to: usize,
+    // snippet, preamble, template, または import 先などの別ファイルなら other
+    origin: &'static str,
+    diagnostic: SourceDiagnosticSer,
+    // ノート中の UTF-16 の範囲に対する修正
+    fixes: Vec<FixSer>,
+}
+
+#[derive(Serialize)]
+pub struct NoteReportSer {
+    path: String,
+    errors: Vec<LintDiagnosticSer>,
+    warnings: Vec<LintDiagnosticSer>,
+}
+
+impl LintDiagnosticSer {
+    // ? 数式をコンパイルした直後の World で変換する
+    pub fn new(d: &LintDiagnostic, markdown: &str, world: &WasmWorld) -> Self {
+        let from = char_position_at(markdown, d.range.start);
+        let diagnostic = SourceDiagnosticSer::from_diag(&d.diagnostic, world);
+
+        LintDiagnosticSer {
+            kind: d.kind,
+            id: d.id.clone(),
+            line: from.line,
+            ch: from.ch,
+            from: from.offset,
+            to: char_position_at(markdown, d.range.end).offset,
+            origin: match d.origin {
+                Some(Origin::Snippet(_)) => "snippet",
+                Some(Origin::Preamble(_)) => "preamble",
+                Some(Origin::Template) => "template",
+                None => "other",
+            },
+            diagnostic,
+            fixes: d
+                .fixes
+                .iter()
+                .map(|fix| FixSer::new(fix, markdown))
+                .collect(),
+        }
+    }
+}
+
+impl From<NoteReport<LintDiagnosticSer>> for NoteReportSer {
+    fn from(report: NoteReport<LintDiagnosticSer>) -> Self {
+        NoteReportSer {
+            path: report.path,
+            errors: report.errors,
+            warnings: report.warnings,
+        }
+    }
+}
@@ -0,0 +1,19 @@
+use serde::Serialize;
+
+use crate::analyzer::matrix::TableEdit;
+use crate::utils::char_position::utf8_to_utf16_offset;
+
+#[derive(Serialize)]
+pub struct TableEditSer {
+    pub text: String,
+    pub cursor: usize,
+}
+
+impl From<&TableEdit> for TableEditSer {
+    fn from(edit: &TableEdit) -> Self {
+        TableEditSer {
+            cursor: utf8_to_utf16_offset(&edit.text, edit.cursor),
+            text: edit.text.clone(),
+        }
+    }
+}
@@ -1,7 +1,13 @@
 pub mod bracket;
 pub mod diagnostic;
+pub mod fetch;
 pub mod font;
+pub mod lint;
+pub mod matrix;
+pub mod needs;
 pub mod package;
 pub mod pdf;
 pub mod processor;
+pub mod snippet;
 pub mod svg;
+pub mod syntax;
@@ -0,0 +1,21 @@
+use serde::Serialize;
+use serde_wasm_bindgen::to_value;
+use wasm_bindgen::JsValue;
+
+use typst::syntax::FileId;
+
+use crate::utils::package::file_key;
+
+#[derive(Serialize)]
+struct NeedsFilesSer {
+    needs: Vec<String>,
+}
+
+// ホストがファイルを用意してから再実行するための結果
+pub fn needs_files(ids: Vec<FileId>) -> Result<JsValue, JsValue> {
+    let mut needs: Vec<String> = ids.into_iter().map(file_key).collect();
+    needs.sort();
+
+    let result = NeedsFilesSer { needs };
+    Ok(to_value(&result)?)
+}
@@ -1,6 +1,9 @@
 use serde::Serialize;
 
-use typst::syntax::package::PackageSpec;
+use typst::{
+    ecow::EcoString,
+    syntax::package::{PackageManifest, PackageSpec},
+};
 
 #[derive(Serialize)]
 pub struct PackageSpecSer {
@@ -18,3 +21,75 @@ impl From<&PackageSpec> for PackageSpecSer {
         }
     }
 }
+
+#[derive(Serialize)]
+pub struct StoreErrorSer {
+    pub path: String,
+    pub message: String,
+}
+
+#[derive(Serialize)]
+pub struct PackageManifestSer {
+    pub spec: PackageSpecSer,
+    pub entrypoint: String,
+    pub description: Option<String>,
+    pub authors: Vec<String>,
+    pub license: Option<String>,
+    pub homepage: Option<String>,
+    pub repository: Option<String>,
+    pub keywords: Vec<String>,
+    pub categories: Vec<String>,
+    pub compiler: Option<String>,
+    pub exclude: Vec<String>,
+}
+
+impl PackageManifestSer {
+    pub fn from_manifest(spec: &PackageSpec, manifest: &PackageManifest) -> Self {
+        let info = &manifest.package;
+        let strings = |v: &[EcoString]| v.iter().map(ToString::to_string).collect();
+
+        PackageManifestSer {
+            spec: spec.into(),
+            entrypoint: info.entrypoint.to_string(),
+            description: info.description.as_ref().map(ToString::to_string),
+            authors: strings(&info.authors),
+            license: info.license.as_ref().map(ToString::to_string),
+            homepage: info.homepage.as_ref().map(ToString::to_string),
+            repository: info.repository.as_ref().map(ToString::to_string),
+            keywords: strings(&info.keywords),
+            categories: strings(&info.categories),
+            compiler: info.compiler.as_ref().map(ToString::to_string),
+            exclude: strings(&info.exclude),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct PackageImportSer {
+    pub source: String,
+    pub from: usize,
+    pub to: usize,
+    pub spec: Option<PackageSpecSer>,
+    pub installed: bool,
+    // 導入済みの最新バージョン
+    pub latest: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct BundleImportSer {
+    pub packages: Vec<PackageSpecSer>,
+    pub errors: Vec<StoreErrorSer>,
+}
+
+#[derive(Serialize)]
+pub struct PackageDependencySer {
+    pub spec: PackageSpecSer,
+    pub present: bool,
+    pub dependencies: Vec<PackageSpecSer>,
+}
+
+#[derive(Serialize)]
+pub struct FailedFileSer {
+    pub path: String,
+    pub message: String,
+}
@@ -1,30 +1,77 @@
 use ecow::EcoVec;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_wasm_bindgen::to_value;
 use wasm_bindgen::JsValue;
 
-use typst::{diag::SourceDiagnostic, ecow};
+use typst::{
+    diag::{Severity, SourceDiagnostic},
+    ecow,
+    foundations::Dict,
+};
 
+use crate::report::group_by_file;
 use crate::serde::diagnostic::SourceDiagnosticSer;
 use crate::world::WasmWorld;
 
+#[derive(Deserialize, Default)]
+pub struct PdfOptionsDes {
+    // "error" または "warning" ならその重大度の診断だけを返す
+    #[serde(default)]
+    pub severity: Option<String>,
+    // `sys.inputs`
+    #[serde(default)]
+    pub inputs: Dict,
+}
+
+#[derive(Serialize)]
+struct FileDiagnosticsSer {
+    // 位置のない診断なら None
+    path: Option<String>,
+    // `@namespace/name:version`
+    package: Option<String>,
+    diags: Vec<SourceDiagnosticSer>,
+}
+
 #[derive(Serialize)]
 struct PdfResultSer {
     pdf: Vec<u8>,
-    diags: Vec<SourceDiagnosticSer>,
+    files: Vec<FileDiagnosticsSer>,
+}
+
+impl PdfOptionsDes {
+    pub fn severity(&self) -> Result<Option<Severity>, JsValue> {
+        match self.severity.as_deref() {
+            None => Ok(None),
+            Some("error") => Ok(Some(Severity::Error)),
+            Some("warning") => Ok(Some(Severity::Warning)),
+            Some(other) => Err(JsValue::from_str(&format!("unknown severity: {}", other))),
+        }
+    }
 }
 
 pub fn pdf(
     pdf: Vec<u8>,
     diags: EcoVec<SourceDiagnostic>,
     world: &WasmWorld,
+    severity: Option<Severity>,
 ) -> Result<JsValue, JsValue> {
-    let result = PdfResultSer {
-        pdf,
-        diags: diags
-            .iter()
-            .map(|d| SourceDiagnosticSer::from_diag(d, world))
-            .collect(),
-    };
+    let files: Vec<FileDiagnosticsSer> = group_by_file(world, &diags, severity)
+        .into_iter()
+        .map(|group| FileDiagnosticsSer {
+            path: group
+                .id
+                .map(|id| id.vpath().as_rootless_path().to_string_lossy().into_owned()),
+            package: group
+                .id
+                .and_then(|id| id.package().map(|spec| spec.to_string())),
+            diags: group
+                .diagnostics
+                .iter()
+                .map(|d| SourceDiagnosticSer::from_diag(d, world))
+                .collect(),
+        })
+        .collect();
+
+    let result = PdfResultSer { pdf, files };
     Ok(to_value(&result)?)
 }
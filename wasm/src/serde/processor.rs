@@ -4,5 +4,8 @@ use serde::Deserialize;
 pub struct ProcessorDes {
     pub kind: String,
     pub id: String,
+    // `{CODE}` を含む format そのもの
     pub format: String,
+    #[serde(default, rename = "noPreamble")]
+    pub no_preamble: bool,
 }
@@ -0,0 +1,41 @@
+use serde::Serialize;
+
+use crate::parser::snippet::Expansion;
+use crate::serde::syntax::SelectionSer;
+
+#[derive(Serialize)]
+pub struct TabstopSer {
+    pub index: Option<usize>,
+    pub ranges: Vec<SelectionSer>,
+}
+
+#[derive(Serialize)]
+pub struct ExpansionSer {
+    pub text: String,
+    // カーソル直前のテキストのうち，置き換える範囲の開始位置
+    pub replace_from: usize,
+    pub tabstops: Vec<TabstopSer>,
+}
+
+impl ExpansionSer {
+    pub fn from_expansion(expansion: &Expansion, replace_from: usize) -> Self {
+        let text = &expansion.text;
+
+        ExpansionSer {
+            text: text.clone(),
+            replace_from,
+            tabstops: expansion
+                .tabstops
+                .iter()
+                .map(|t| TabstopSer {
+                    index: t.index,
+                    ranges: t
+                        .ranges
+                        .iter()
+                        .map(|r| SelectionSer::from_range(r.clone(), text))
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+}
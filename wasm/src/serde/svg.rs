@@ -6,6 +6,7 @@ use wasm_bindgen::JsValue;
 use typst::{diag::SourceDiagnostic, ecow};
 
 use crate::serde::diagnostic::SourceDiagnosticSer;
+use crate::template::Filled;
 use crate::world::WasmWorld;
 
 #[derive(Serialize)]
@@ -18,12 +19,16 @@ pub fn svg(
     svg: String,
     diags: EcoVec<SourceDiagnostic>,
     world: &WasmWorld,
+    filled: Option<&Filled>,
 ) -> Result<JsValue, JsValue> {
     let result = SvgResultSer {
         svg,
         diags: diags
             .iter()
-            .map(|d| SourceDiagnosticSer::from_diag(d, world))
+            .map(|d| match filled {
+                Some(filled) => SourceDiagnosticSer::from_filled_diag(d, world, filled),
+                None => SourceDiagnosticSer::from_diag(d, world),
+            })
             .collect(),
     };
     Ok(to_value(&result)?)
@@ -0,0 +1,43 @@
+use std::ops::Range;
+
+use serde::Serialize;
+
+use typst::syntax::LinkedNode;
+
+use crate::utils::char_position::utf8_to_utf16_offset;
+
+#[derive(Serialize)]
+pub struct SyntaxNodeSer {
+    pub kind: String,
+
+    pub from: usize,
+    pub to: usize,
+}
+
+impl SyntaxNodeSer {
+    pub fn from_node(node: &LinkedNode, code: &str) -> Self {
+        let range = node.range();
+
+        SyntaxNodeSer {
+            kind: format!("{:?}", node.kind()),
+
+            from: utf8_to_utf16_offset(code, range.start),
+            to: utf8_to_utf16_offset(code, range.end),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct SelectionSer {
+    pub from: usize,
+    pub to: usize,
+}
+
+impl SelectionSer {
+    pub fn from_range(range: Range<usize>, code: &str) -> Self {
+        SelectionSer {
+            from: utf8_to_utf16_offset(code, range.start),
+            to: utf8_to_utf16_offset(code, range.end),
+        }
+    }
+}
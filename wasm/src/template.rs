@@ -0,0 +1,272 @@
+use std::ops::Range;
+
+use crate::analyzer::fixes::{Fix, TextEdit};
+
+// プロセッサーの format (`{CODE}` を含む) と前文
+#[derive(Clone)]
+pub struct Template {
+    // noPreamble なら None
+    pub preamble: Option<String>,
+    pub format: String,
+}
+
+// 元のテキストから取り除かれた部分 (結果での位置, バイト数)
+#[derive(Clone, Default, Debug)]
+pub struct OffsetMap {
+    removed: Vec<(usize, usize)>,
+}
+
+// 埋め込んだテキストの範囲がどこに由来するか
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Origin {
+    // ユーザーの数式中のバイト範囲
+    Snippet(Range<usize>),
+    // 前文中のバイト範囲
+    Preamble(Range<usize>),
+    Template,
+}
+
+pub struct Filled {
+    pub text: String,
+    // 埋め込む前の数式
+    code: String,
+    preamble: Option<Range<usize>>,
+    snippet: Option<Range<usize>>,
+    map: OffsetMap,
+}
+
+impl OffsetMap {
+    // ? 位置の昇順に追加する
+    pub fn remove(&mut self, at: usize, len: usize) {
+        if len != 0 {
+            self.removed.push((at, len));
+        }
+    }
+
+    // 先頭の `len` バイトを取り除いたことを記録する
+    pub fn strip_prefix(&mut self, len: usize) {
+        for (at, _) in &mut self.removed {
+            *at = at.saturating_sub(len);
+        }
+        if len != 0 {
+            self.removed.insert(0, (0, len));
+        }
+    }
+
+    pub fn to_original(&self, offset: usize) -> usize {
+        offset
+            + self
+                .removed
+                .iter()
+                .take_while(|(at, _)| *at <= offset)
+                .map(|(_, len)| len)
+                .sum::<usize>()
+    }
+}
+
+impl Template {
+    pub fn new(preamble: Option<&str>, format: &str) -> Self {
+        Self {
+            preamble: preamble.map(str::to_string),
+            format: format.to_string(),
+        }
+    }
+
+    // プラグインが描画するソースを，各部分の位置を覚えながら作る
+    // `width` は fitToParentWidth の幅 (pt)
+    pub fn fill(&self, code: &str, display: bool, width: Option<f64>) -> Filled {
+        // ? JS の String.replace と同じく，最初の `{CODE}` だけを置き換える
+        let (before, after) = match self.format.split_once("{CODE}") {
+            Some((before, after)) => (before, Some(after)),
+            None => (self.format.as_str(), None),
+        };
+        let br = |s: &str| {
+            if display {
+                s.replace("<br>", "\n")
+            } else {
+                s.to_string()
+            }
+        };
+
+        let mut preamble = self.preamble.as_deref().map(br);
+        let mut before = br(before);
+        let mut after = after.map(br);
+        if width.is_some() {
+            // 最初の `width: auto` を置き換える
+            let target = [preamble.as_mut(), Some(&mut before), after.as_mut()]
+                .into_iter()
+                .flatten()
+                .find(|s| s.contains("width: auto"));
+            if let Some(s) = target {
+                *s = s.replacen("width: auto", "width: WIDTH", 1);
+            }
+        }
+
+        let mut text = String::new();
+        if let Some(width) = width {
+            text.push_str(&format!("#let WIDTH = {}pt\n", width));
+        }
+
+        let preamble = preamble.map(|preamble| {
+            let start = text.len();
+            text.push_str(&preamble);
+            let range = start..text.len();
+            text.push('\n');
+            range
+        });
+        text.push_str(&before);
+
+        let mut map = OffsetMap::default();
+        let snippet = after.map(|after| {
+            let start = text.len();
+            if display {
+                let mut rest = code;
+                while let Some(i) = rest.find("<br>") {
+                    text.push_str(&rest[..i]);
+                    text.push('\n');
+                    map.remove(text.len() - start, "<br>".len() - 1);
+                    rest = &rest[i + "<br>".len()..];
+                }
+                text.push_str(rest);
+            } else {
+                text.push_str(code);
+            }
+            let range = start..text.len();
+            text.push_str(&after);
+            range
+        });
+
+        Filled {
+            text,
+            code: code.to_string(),
+            preamble,
+            snippet,
+            map,
+        }
+    }
+}
+
+impl Filled {
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    pub fn preamble(&self) -> Option<&str> {
+        self.preamble.clone().map(|range| &self.text[range])
+    }
+
+    // 埋め込んだテキスト中のバイト範囲の由来
+    // ? 数式から始まる範囲は数式の末尾で切る
+    pub fn origin(&self, range: Range<usize>) -> Origin {
+        // ? 数式の直後の幅 0 の範囲 (閉じ忘れなど) も数式に含める
+        if let Some(snippet) = &self.snippet
+            && (snippet.start..=snippet.end).contains(&range.start)
+            && (range.start < snippet.end || range.is_empty())
+        {
+            let start = range.start - snippet.start;
+            let end = range.end.clamp(range.start, snippet.end) - snippet.start;
+            return Origin::Snippet(self.map.to_original(start)..self.map.to_original(end));
+        }
+
+        if let Some(preamble) = &self.preamble
+            && preamble.contains(&range.start)
+        {
+            let end = range.end.clamp(range.start, preamble.end);
+            return Origin::Preamble(range.start - preamble.start..end - preamble.start);
+        }
+
+        Origin::Template
+    }
+
+    // 埋め込んだテキストに対する修正を，数式に対する修正に戻す
+    // ? 数式の外に触れるなら None
+    pub fn fix(&self, fix: &Fix) -> Option<Fix> {
+        let snippet = self.snippet.as_ref()?;
+        let edits = fix
+            .edits
+            .iter()
+            .map(|edit| {
+                (snippet.start <= edit.range.start && edit.range.end <= snippet.end).then(|| {
+                    let start = self.map.to_original(edit.range.start - snippet.start);
+                    let end = self.map.to_original(edit.range.end - snippet.start);
+                    TextEdit {
+                        range: start..end,
+                        text: edit.text.clone(),
+                    }
+                })
+            })
+            .collect::<Option<_>>()?;
+
+        Some(Fix {
+            title: fix.title.clone(),
+            edits,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit(range: Range<usize>, text: &str) -> Fix {
+        Fix {
+            title: String::new(),
+            edits: vec![TextEdit {
+                range,
+                text: text.to_string(),
+            }],
+        }
+    }
+
+    #[test]
+    fn origin() {
+        let template = Template::new(Some("#set text(red)"), "${CODE}$");
+        let filled = template.fill("x + y", false, None);
+        assert_eq!(filled.text, "#set text(red)\n$x + y$");
+
+        let at = |s: &str| filled.text.find(s).unwrap();
+        assert_eq!(filled.origin(at("y")..at("y") + 1), Origin::Snippet(4..5));
+        assert_eq!(
+            filled.origin(at("red")..at("red") + 3),
+            Origin::Preamble(10..13)
+        );
+        assert_eq!(filled.origin(at("$")..at("$") + 1), Origin::Template);
+        // ? 数式の直後の幅 0 の範囲は数式に含め，数式からはみ出す範囲は数式の末尾で切る
+        let end = at("y") + 1;
+        assert_eq!(filled.origin(end..end), Origin::Snippet(5..5));
+        assert_eq!(filled.origin(at("y")..end + 1), Origin::Snippet(4..5));
+    }
+
+    #[test]
+    fn origin_with_br() {
+        let filled = Template::new(None, "${CODE}$").fill("a<br>b", true, None);
+        assert_eq!(filled.text, "$a\nb$");
+
+        let b = filled.text.find('b').unwrap();
+        assert_eq!(filled.origin(b..b + 1), Origin::Snippet(5..6));
+    }
+
+    #[test]
+    fn width() {
+        let template = Template::new(Some("#set page(width: auto)"), "{CODE}");
+        let filled = template.fill("x", false, Some(100.0));
+        assert_eq!(
+            filled.text,
+            "#let WIDTH = 100pt\n#set page(width: WIDTH)\nx"
+        );
+        assert_eq!(filled.preamble(), Some("#set page(width: WIDTH)"));
+    }
+
+    #[test]
+    fn fix() {
+        let filled = Template::new(Some("#let a = 1"), "$ {CODE} $").fill("a<br>b c", true, None);
+        let c = filled.text.find('c').unwrap();
+
+        let fixed = filled.fix(&edit(c..c + 1, "d")).unwrap();
+        assert_eq!(fixed.edits[0].range, 7..8);
+        assert_eq!(fixed.edits[0].text, "d");
+        // ? 数式の外に触れる修正は戻せない
+        assert!(filled.fix(&edit(0..1, "")).is_none());
+        assert!(filled.fix(&edit(c..filled.text.len(), "")).is_none());
+    }
+}
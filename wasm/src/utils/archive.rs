@@ -0,0 +1,284 @@
+use std::{io::Read, path::Path};
+
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use rustc_hash::FxHashMap;
+use tar::{Archive, Builder, EntryType, Header};
+use typst::ecow::{EcoString, eco_format};
+
+// 展開後の合計サイズの上限
+// ? gzip 爆弾でメモリを使い切らないため
+const MAX_UNPACKED_SIZE: u64 = 256 * 1024 * 1024;
+
+// tar.gz を展開し，パッケージ内のパスと中身の組を返す
+pub fn unpack_targz(bytes: &[u8]) -> Result<FxHashMap<String, Vec<u8>>, EcoString> {
+    unpack_targz_with_limit(bytes, MAX_UNPACKED_SIZE)
+}
+
+fn unpack_targz_with_limit(
+    bytes: &[u8],
+    limit: u64,
+) -> Result<FxHashMap<String, Vec<u8>>, EcoString> {
+    let mut archive = Archive::new(GzDecoder::new(bytes));
+    let entries = archive
+        .entries()
+        .map_err(|e| eco_format!("failed to read archive: {}", e))?;
+
+    let mut files = FxHashMap::default();
+    let mut links = Vec::new();
+    let mut size = Size { total: 0, limit };
+    for entry in entries {
+        let mut entry = entry.map_err(|e| eco_format!("failed to read archive entry: {}", e))?;
+        let path = normalize(
+            &entry
+                .path()
+                .map_err(|e| eco_format!("invalid path: {}", e))?,
+        )?;
+
+        match entry.header().entry_type() {
+            EntryType::Regular | EntryType::Continuous => {
+                // ? ヘッダーのサイズは信用せず，上限を超える分は読まない
+                let mut data = Vec::new();
+                entry
+                    .by_ref()
+                    .take(size.limit - size.total + 1)
+                    .read_to_end(&mut data)
+                    .map_err(|e| eco_format!("failed to read {}: {}", path, e))?;
+                size.add(data.len())?;
+                files.insert(path, data);
+            }
+            kind @ (EntryType::Symlink | EntryType::Link) => {
+                let target = entry
+                    .link_name()
+                    .map_err(|e| eco_format!("invalid link target of {}: {}", path, e))?
+                    .ok_or_else(|| eco_format!("link without target: {}", path))?;
+                // ? シンボリックリンクはリンク自身のディレクトリ，ハードリンクはアーカイブのルートから辿る
+                let target = match kind {
+                    EntryType::Symlink => Path::new(&path)
+                        .parent()
+                        .unwrap_or(Path::new(""))
+                        .join(target),
+                    _ => target.into_owned(),
+                };
+                let target = normalize(&target)?;
+                // ? 自身を含むディレクトリへのリンクは展開が終わらない
+                if target.is_empty() || path == target || path.starts_with(&format!("{}/", target))
+                {
+                    return Err(eco_format!("link loop: {} -> {}", path, target));
+                }
+                links.push((path, target));
+            }
+            // ディレクトリなどは暗黙に作られる
+            _ => {}
+        }
+    }
+
+    resolve_links(&mut files, &links, &mut size)?;
+
+    Ok(files)
+}
+
+struct Size {
+    total: u64,
+    limit: u64,
+}
+
+impl Size {
+    fn add(&mut self, len: usize) -> Result<(), EcoString> {
+        self.total += len as u64;
+        if self.limit < self.total {
+            return Err(eco_format!(
+                "archive exceeds {} bytes when unpacked",
+                self.limit
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+// リンクを辿れる深さの上限
+const MAX_LINK_DEPTH: usize = 8;
+
+// リンク先のファイル，またはディレクトリ以下のファイルをリンクの位置に複製する
+// ? リンク先が別のリンクやその下を指すこともあるので，増えなくなるまで繰り返す
+// ? 複製した分も展開後のサイズに数える
+fn resolve_links(
+    files: &mut FxHashMap<String, Vec<u8>>,
+    links: &[(String, String)],
+    size: &mut Size,
+) -> Result<(), EcoString> {
+    for _ in 0..MAX_LINK_DEPTH {
+        let mut added = false;
+        for (path, target) in links {
+            let prefix = format!("{}/", target);
+            let copies: Vec<(String, Vec<u8>)> = files
+                .iter()
+                .filter_map(|(file, data)| {
+                    // `target/a.typ` なら `/a.typ`
+                    let rest = if file == target || file.starts_with(&prefix) {
+                        &file[target.len()..]
+                    } else {
+                        return None;
+                    };
+                    let copy = format!("{}{}", path, rest);
+                    (!files.contains_key(&copy)).then(|| (copy, data.clone()))
+                })
+                .collect();
+            for (_, data) in &copies {
+                size.add(data.len())?;
+            }
+            added |= !copies.is_empty();
+            files.extend(copies);
+        }
+        if !added {
+            break;
+        }
+    }
+
+    // 何も指していないリンクは壊れたパッケージとして扱う
+    if let Some((path, target)) = links.iter().find(|(path, _)| {
+        let prefix = format!("{}/", path);
+        !files.contains_key(path) && !files.keys().any(|file| file.starts_with(&prefix))
+    }) {
+        return Err(eco_format!(
+            "failed to resolve link: {} -> {}",
+            path,
+            target
+        ));
+    }
+
+    Ok(())
+}
+
+// パスと中身の組から tar.gz を作る
+pub fn pack_targz<'a, I>(files: I) -> Result<Vec<u8>, EcoString>
+where
+    I: IntoIterator<Item = (String, &'a [u8])>,
+{
+    let mut builder = Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    for (path, data) in files {
+        let mut header = Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        // ? 同じ内容なら同じアーカイブになるよう，更新日時は固定する
+        header.set_mtime(0);
+        header.set_entry_type(EntryType::Regular);
+        builder
+            .append_data(&mut header, &path, data)
+            .map_err(|e| eco_format!("failed to add {}: {}", path, e))?;
+    }
+
+    builder
+        .into_inner()
+        .and_then(|gz| gz.finish())
+        .map_err(|e| eco_format!("failed to write archive: {}", e))
+}
+
+// `./` や `..` を解決し，ルートの外を指すパスを拒否する
+fn normalize(path: &Path) -> Result<String, EcoString> {
+    let mut parts: Vec<&str> = Vec::new();
+    for component in path.components() {
+        match component {
+            std::path::Component::Normal(p) => parts.push(
+                p.to_str()
+                    .ok_or_else(|| eco_format!("non UTF-8 path: {}", path.display()))?,
+            ),
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                parts
+                    .pop()
+                    .ok_or_else(|| eco_format!("path escapes the package: {}", path.display()))?;
+            }
+            _ => return Err(eco_format!("absolute path in archive: {}", path.display())),
+        }
+    }
+
+    Ok(parts.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    enum Entry {
+        File(&'static str, &'static str),
+        Symlink(&'static str, &'static str),
+    }
+
+    fn targz(entries: &[Entry]) -> Vec<u8> {
+        let mut builder = Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        for entry in entries {
+            let mut header = Header::new_gnu();
+            match *entry {
+                Entry::File(path, text) => {
+                    header.set_size(text.len() as u64);
+                    header.set_entry_type(EntryType::Regular);
+                    builder
+                        .append_data(&mut header, path, text.as_bytes())
+                        .unwrap();
+                }
+                Entry::Symlink(path, target) => {
+                    header.set_size(0);
+                    header.set_entry_type(EntryType::Symlink);
+                    builder.append_link(&mut header, path, target).unwrap();
+                }
+            }
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn text(files: &FxHashMap<String, Vec<u8>>, path: &str) -> Option<String> {
+        files
+            .get(path)
+            .map(|data| String::from_utf8(data.clone()).unwrap())
+    }
+
+    #[test]
+    fn links() {
+        let files = unpack_targz(&targz(&[
+            // ? リンクが先に並んでいても解決できる
+            Entry::Symlink("lib.typ", "src/lib.typ"),
+            Entry::Symlink("alias.typ", "lib.typ"),
+            Entry::Symlink("vendor", "src/util"),
+            Entry::File("src/lib.typ", "lib"),
+            Entry::File("src/util/a.typ", "a"),
+            Entry::Symlink("src/util/b.typ", "../lib.typ"),
+        ]))
+        .unwrap();
+
+        assert_eq!(text(&files, "lib.typ").as_deref(), Some("lib"));
+        assert_eq!(text(&files, "alias.typ").as_deref(), Some("lib"));
+        assert_eq!(text(&files, "vendor/a.typ").as_deref(), Some("a"));
+        assert_eq!(text(&files, "vendor/b.typ").as_deref(), Some("lib"));
+    }
+
+    #[test]
+    fn broken_links() {
+        let dangling = targz(&[Entry::Symlink("lib.typ", "missing.typ")]);
+        assert!(unpack_targz(&dangling).is_err());
+
+        let cycle = targz(&[
+            Entry::Symlink("a.typ", "b.typ"),
+            Entry::Symlink("b.typ", "a.typ"),
+        ]);
+        assert!(unpack_targz(&cycle).is_err());
+
+        let parent = targz(&[Entry::File("a/b.typ", ""), Entry::Symlink("a/up", "..")]);
+        assert!(unpack_targz(&parent).is_err());
+    }
+
+    #[test]
+    fn size_limit() {
+        let archive = targz(&[Entry::File("a.typ", "12345"), Entry::File("b.typ", "6789")]);
+        assert!(unpack_targz_with_limit(&archive, 9).is_ok());
+        assert!(unpack_targz_with_limit(&archive, 8).is_err());
+
+        // ? リンクで複製される分も数える
+        let linked = targz(&[
+            Entry::File("a.typ", "12345"),
+            Entry::Symlink("b.typ", "a.typ"),
+        ]);
+        assert!(unpack_targz_with_limit(&linked, 10).is_ok());
+        assert!(unpack_targz_with_limit(&linked, 9).is_err());
+    }
+}
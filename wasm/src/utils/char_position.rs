@@ -31,3 +31,36 @@ pub fn precompute_char_positions(src: &str) -> Vec<CharPosition> {
 
     positions
 }
+
+// UTF-8 のバイトオフセットの位置
+pub fn char_position_at(src: &str, utf8: usize) -> CharPosition {
+    let before = &src[..src.floor_char_boundary(utf8.min(src.len()))];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+
+    CharPosition {
+        line: before.matches('\n').count(),
+        ch: before[line_start..].chars().map(char::len_utf16).sum(),
+        offset: before.chars().map(char::len_utf16).sum(),
+    }
+}
+
+// ? CodeMirror の UTF-16 オフセットを UTF-8 のバイトオフセットに変換する
+pub fn utf16_to_utf8_offset(src: &str, utf16: usize) -> usize {
+    let mut offset = 0;
+
+    for (i, c) in src.char_indices() {
+        if offset >= utf16 {
+            return i;
+        }
+        offset += c.len_utf16();
+    }
+
+    src.len()
+}
+
+pub fn utf8_to_utf16_offset(src: &str, utf8: usize) -> usize {
+    src[..src.floor_char_boundary(utf8.min(src.len()))]
+        .chars()
+        .map(char::len_utf16)
+        .sum()
+}
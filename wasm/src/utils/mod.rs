@@ -1 +1,4 @@
+pub mod archive;
 pub mod char_position;
+pub mod package;
+pub mod symbols;
@@ -0,0 +1,122 @@
+use std::str::FromStr;
+
+use rustc_hash::FxHashMap;
+use typst::{
+    ecow::{EcoString, eco_format},
+    syntax::{
+        FileId, VirtualPath,
+        package::{PackageManifest, PackageSpec, PackageVersion, VersionlessPackageSpec},
+    },
+};
+
+use crate::utils::archive::unpack_targz;
+
+// `@namespace/name/version/path` を PackageSpec とパッケージ内のパスに分ける
+pub fn parse_package_key(key: &str) -> Result<(PackageSpec, &str), EcoString> {
+    let p = key
+        .strip_prefix('@')
+        .ok_or_else(|| eco_format!("package key must start with `@`: {}", key))?;
+
+    let mut p_parts = p.splitn(4, '/');
+    let (Some(namespace), Some(name), Some(version_str), Some(vpath)) = (
+        p_parts.next(),
+        p_parts.next(),
+        p_parts.next(),
+        p_parts.next(),
+    ) else {
+        return Err(eco_format!(
+            "package key must be `@namespace/name/version/path`: {}",
+            key
+        ));
+    };
+
+    let versionless = VersionlessPackageSpec::from_str(&format!("@{}/{}", namespace, name))?;
+    let version = PackageVersion::from_str(version_str)?;
+
+    Ok((versionless.at(version), vpath))
+}
+
+// `@namespace/name/version/path` またはボールト内のパスを FileId にする
+pub fn parse_file_key(key: &str) -> Result<FileId, EcoString> {
+    if key.starts_with('@') {
+        let (spec, vpath) = parse_package_key(key)?;
+        Ok(FileId::new(Some(spec), VirtualPath::new(vpath)))
+    } else {
+        Ok(FileId::new(None, VirtualPath::new(key)))
+    }
+}
+
+// parse_file_key の逆
+pub fn file_key(id: FileId) -> String {
+    let vpath = id.vpath().as_rootless_path().to_string_lossy();
+    match id.package() {
+        Some(spec) => format!(
+            "@{}/{}/{}/{}",
+            spec.namespace, spec.name, spec.version, vpath
+        ),
+        None => vpath.into_owned(),
+    }
+}
+
+// `@namespace/name/version`
+pub fn parse_package_spec(spec: &str) -> Result<PackageSpec, EcoString> {
+    let key = format!("{}/", spec.trim_end_matches('/'));
+    match parse_package_key(&key) {
+        Ok((spec, "")) => Ok(spec),
+        _ => Err(eco_format!(
+            "package spec must be `@namespace/name/version`: {}",
+            spec
+        )),
+    }
+}
+
+// typst.toml を読み，名前とバージョン，対応するコンパイラ，エントリーポイントを検証する
+pub fn validate_manifest<F>(
+    spec: &PackageSpec,
+    manifest: &[u8],
+    exists: F,
+) -> Result<PackageManifest, EcoString>
+where
+    F: Fn(&str) -> bool,
+{
+    let text = std::str::from_utf8(manifest)
+        .map_err(|_| eco_format!("typst.toml of {} is not UTF-8", spec))?;
+    let manifest: PackageManifest = toml::from_str(text)
+        .map_err(|e| eco_format!("failed to parse typst.toml of {}: {}", spec, e.message()))?;
+
+    manifest.validate(spec)?;
+
+    let entrypoint = manifest.package.entrypoint.trim_start_matches("./");
+    if !exists(entrypoint) {
+        return Err(eco_format!(
+            "entrypoint {} of {} does not exist",
+            manifest.package.entrypoint,
+            spec
+        ));
+    }
+
+    Ok(manifest)
+}
+
+// tar.gz を展開し，パッケージとして検証する
+pub fn unpack_package(
+    spec: &PackageSpec,
+    targz: &[u8],
+) -> Result<FxHashMap<String, Vec<u8>>, EcoString> {
+    let files = unpack_targz(targz)?;
+    validate_package_files(spec, &files)?;
+
+    Ok(files)
+}
+
+// 展開済みのパッケージのファイル群を検証する
+pub fn validate_package_files(
+    spec: &PackageSpec,
+    files: &FxHashMap<String, Vec<u8>>,
+) -> Result<PackageManifest, EcoString> {
+    let manifest = files
+        .get("typst.toml")
+        .ok_or_else(|| eco_format!("{} is missing typst.toml", spec))?;
+
+    validate_manifest(spec, manifest, |p| files.contains_key(p))
+}
@@ -0,0 +1,34 @@
+use std::sync::LazyLock;
+
+use rustc_hash::FxHashMap;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct SymbolDes {
+    #[serde(rename = "latexName")]
+    latex_name: String,
+}
+
+// プラグインのシンボルデータ (`src/data/symbols.json`) から作った，LaTeX のコマンド名 (`\` なし) から Typst の記号名への対応
+static LATEX: LazyLock<FxHashMap<String, Vec<String>>> = LazyLock::new(|| {
+    let symbols: FxHashMap<String, SymbolDes> =
+        serde_json::from_str(include_str!("../../../src/data/symbols.json")).unwrap_or_default();
+
+    let mut map: FxHashMap<String, Vec<String>> = FxHashMap::default();
+    for (name, symbol) in symbols {
+        if let Some(command) = symbol.latex_name.strip_prefix('\\') {
+            map.entry(command.to_string()).or_default().push(name);
+        }
+    }
+    for names in map.values_mut() {
+        // ? 短い名前を優先する
+        names.sort_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)));
+    }
+
+    map
+});
+
+// `command` は `\` を除いた LaTeX のコマンド名
+pub fn latex_to_typst(command: &str) -> &'static [String] {
+    LATEX.get(command).map(Vec::as_slice).unwrap_or_default()
+}
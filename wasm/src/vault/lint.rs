@@ -0,0 +1,171 @@
+use std::ops::Range;
+
+use rustc_hash::FxHashSet;
+
+use typst::{World, WorldExt, diag::SourceDiagnostic, syntax::Span};
+
+use crate::analyzer::fixes::{Fix, TextEdit, main_fixes};
+use crate::compiler::{CompileError, Compiled, Compiler};
+use crate::template::{Filled, Origin};
+use crate::vault::{
+    markdown::{MathSegment, math_segments},
+    settings::{Resolved, Settings},
+};
+
+pub struct LintDiagnostic {
+    pub kind: &'static str,
+    pub id: String,
+    // ノート中のバイト範囲
+    // ? 数式の外 (前文・テンプレート・他のファイル) が原因なら数式全体
+    pub range: Range<usize>,
+    // None なら import 先などの別ファイル
+    pub origin: Option<Origin>,
+    pub diagnostic: SourceDiagnostic,
+    // ノート中のバイト範囲に対する修正
+    pub fixes: Vec<Fix>,
+}
+
+pub struct NoteReport<D = LintDiagnostic> {
+    pub path: String,
+    pub errors: Vec<D>,
+    pub warnings: Vec<D>,
+}
+
+// ノート (パス, Markdown) ごとに，全ての数式をプロセッサーを通してコンパイルする
+// ? 読み込み待ちのファイルは全てのノートの分をまとめて返す
+// ? 診断の位置は次の数式をコンパイルすると World から引けなくなるので，`convert` でその場で変換する
+pub fn lint_notes<'a, D>(
+    compiler: &mut Compiler,
+    settings: &Settings,
+    notes: impl IntoIterator<Item = (&'a str, &'a str)>,
+    mut convert: impl FnMut(&Compiler, &str, LintDiagnostic) -> D,
+) -> Result<Vec<NoteReport<D>>, CompileError> {
+    let mut reports = Vec::new();
+    let mut needs = FxHashSet::default();
+
+    for (path, markdown) in notes {
+        let mut report = NoteReport {
+            path: path.to_string(),
+            errors: Vec::new(),
+            warnings: Vec::new(),
+        };
+
+        for segment in math_segments(markdown) {
+            let Some(resolved) = settings.resolve(&segment) else {
+                continue;
+            };
+
+            let filled = settings.template(resolved.processor).fill(
+                &resolved.code,
+                resolved.kind == "display",
+                None,
+            );
+            let result = compiler.check(&filled.text, resolved.kind, &resolved.processor.id);
+            let mut diagnostic = |diagnostic| {
+                let located = locate(compiler, &segment, &resolved, &filled, diagnostic);
+                convert(compiler, markdown, located)
+            };
+
+            match result {
+                Ok(Compiled { warnings, .. }) => {
+                    report
+                        .warnings
+                        .extend(warnings.into_iter().map(&mut diagnostic));
+                }
+                Err(CompileError::Diagnostics(errors)) => {
+                    report
+                        .errors
+                        .extend(errors.into_iter().map(&mut diagnostic));
+                }
+                Err(CompileError::NeedsFiles(ids)) => needs.extend(ids),
+                Err(CompileError::NoPages) => report.errors.push(diagnostic(
+                    SourceDiagnostic::error(Span::detached(), "document has no pages"),
+                )),
+            }
+        }
+
+        reports.push(report);
+    }
+
+    if !needs.is_empty() {
+        return Err(CompileError::NeedsFiles(needs.into_iter().collect()));
+    }
+
+    Ok(reports)
+}
+
+// 診断の位置をノート中の位置に戻す
+pub fn locate(
+    compiler: &Compiler,
+    segment: &MathSegment,
+    resolved: &Resolved,
+    filled: &Filled,
+    diagnostic: SourceDiagnostic,
+) -> LintDiagnostic {
+    let world = compiler.world();
+    let origin = (diagnostic.span.id() == Some(world.main()))
+        .then(|| world.range(diagnostic.span))
+        .flatten()
+        .map(|range| filled.origin(range));
+
+    let range = match &origin {
+        Some(Origin::Snippet(range)) => {
+            let start = segment.range.start + resolved.map.to_original(range.start);
+            let end = segment.range.start + resolved.map.to_original(range.end);
+            start..end.min(segment.range.end)
+        }
+        _ => segment.range.clone(),
+    };
+
+    let to_note = |offset| segment.range.start + resolved.map.to_original(offset);
+    let fixes = main_fixes(&diagnostic, world)
+        .iter()
+        .filter_map(|fix| filled.fix(fix))
+        .map(|fix| Fix {
+            title: fix.title,
+            edits: fix
+                .edits
+                .into_iter()
+                .map(|edit| TextEdit {
+                    range: to_note(edit.range.start)..to_note(edit.range.end),
+                    text: edit.text,
+                })
+                .collect(),
+        })
+        .collect();
+
+    LintDiagnostic {
+        kind: resolved.kind,
+        id: resolved.processor.id.clone(),
+        range,
+        origin,
+        diagnostic,
+        fixes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::MemoryFileProvider;
+
+    #[test]
+    fn diagnostics_are_converted_per_segment() {
+        let mut compiler = Compiler::new(Box::new(MemoryFileProvider::new()), 16.0);
+        let markdown = "$x + #undefined$ and $y$";
+
+        let reports = lint_notes(
+            &mut compiler,
+            &Settings::default(),
+            [("note.md", markdown)],
+            |compiler, _, d| (compiler.world().range(d.diagnostic.span), d.range),
+        )
+        .ok()
+        .unwrap();
+
+        // ? 2 つ目の数式をコンパイルした後でも 1 つ目の数式中の位置が分かる
+        let (span, range) = &reports[0].errors[0];
+        assert!(span.is_some());
+        assert_eq!(&markdown[range.clone()], "undefined");
+    }
+}
@@ -0,0 +1,285 @@
+use std::ops::Range;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SegmentKind {
+    Inline,
+    Display,
+    // フェンスの言語名
+    Codeblock(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct MathSegment {
+    pub kind: SegmentKind,
+    pub code: String,
+    // ノート中の `code` のバイト範囲
+    pub range: Range<usize>,
+}
+
+// ノートから数式とコードブロックを取り出す
+// ? Obsidian の挙動に合わせて，インラインコードとフロントマターは飛ばす
+pub fn math_segments(markdown: &str) -> Vec<MathSegment> {
+    let mut segments = Vec::new();
+    let mut offset = skip_frontmatter(markdown);
+    // フェンスの外側のテキストの開始位置
+    let mut text_start = offset;
+
+    while offset < markdown.len() {
+        let line_end = markdown[offset..]
+            .find('\n')
+            .map_or(markdown.len(), |i| offset + i + 1);
+
+        let Some((fence, lang)) = open_fence(&markdown[offset..line_end]) else {
+            offset = line_end;
+            continue;
+        };
+
+        scan_text(markdown, text_start..offset, &mut segments);
+
+        // 閉じフェンスを探す
+        let body_start = line_end;
+        let mut body_end = markdown.len();
+        let mut next = markdown.len();
+        let mut cursor = body_start;
+        while cursor < markdown.len() {
+            let end = markdown[cursor..]
+                .find('\n')
+                .map_or(markdown.len(), |i| cursor + i + 1);
+            if is_close_fence(&markdown[cursor..end], fence) {
+                body_end = cursor;
+                next = end;
+                break;
+            }
+            cursor = end;
+        }
+
+        if !lang.is_empty() {
+            let code = &markdown[body_start..body_end];
+            let code = code.strip_suffix('\n').unwrap_or(code);
+            segments.push(MathSegment {
+                kind: SegmentKind::Codeblock(lang.to_string()),
+                code: code.to_string(),
+                range: body_start..body_start + code.len(),
+            });
+        }
+
+        offset = next;
+        text_start = next;
+    }
+
+    scan_text(markdown, text_start..markdown.len(), &mut segments);
+
+    segments
+}
+
+fn skip_frontmatter(markdown: &str) -> usize {
+    if !markdown.starts_with("---\n") {
+        return 0;
+    }
+
+    let mut offset = 4;
+    while offset < markdown.len() {
+        let end = markdown[offset..]
+            .find('\n')
+            .map_or(markdown.len(), |i| offset + i + 1);
+        if markdown[offset..end].trim_end() == "---" {
+            return end;
+        }
+        offset = end;
+    }
+
+    0
+}
+
+// (フェンス文字, 長さ), 言語名
+fn open_fence(line: &str) -> Option<((char, usize), &str)> {
+    let trimmed = line.trim_start_matches(' ');
+    if line.len() - trimmed.len() > 3 {
+        return None;
+    }
+
+    let ch = trimmed.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = trimmed.chars().take_while(|c| *c == ch).count();
+    if len < 3 {
+        return None;
+    }
+
+    let info = trimmed[len..].trim();
+    // ? バッククォートのフェンスの情報文字列にバッククォートは含められない
+    if ch == '`' && info.contains('`') {
+        return None;
+    }
+
+    Some(((ch, len), info.split_whitespace().next().unwrap_or("")))
+}
+
+fn is_close_fence(line: &str, (ch, len): (char, usize)) -> bool {
+    let trimmed = line.trim_start_matches(' ');
+    if line.len() - trimmed.len() > 3 {
+        return false;
+    }
+
+    let count = trimmed.chars().take_while(|c| *c == ch).count();
+    count >= len && trimmed[count..].trim().is_empty()
+}
+
+fn scan_text(markdown: &str, range: Range<usize>, segments: &mut Vec<MathSegment>) {
+    let bytes = markdown.as_bytes();
+    let mut i = range.start;
+
+    while i < range.end {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'`' => {
+                // インラインコード
+                let run = count_run(bytes, i, range.end, b'`');
+                i = find_run(bytes, i + run, range.end, b'`', run).map_or(i + run, |j| j + run);
+            }
+            b'$' if bytes.get(i + 1) == Some(&b'$') => {
+                let start = i + 2;
+                match find_display_end(bytes, start, range.end) {
+                    Some(end) => {
+                        segments.push(MathSegment {
+                            kind: SegmentKind::Display,
+                            code: markdown[start..end].to_string(),
+                            range: start..end,
+                        });
+                        i = end + 2;
+                    }
+                    None => i = start,
+                }
+            }
+            b'$' => {
+                let start = i + 1;
+                match find_inline_end(bytes, start, range.end) {
+                    Some(end) => {
+                        segments.push(MathSegment {
+                            kind: SegmentKind::Inline,
+                            code: markdown[start..end].to_string(),
+                            range: start..end,
+                        });
+                        i = end + 1;
+                    }
+                    None => i = start,
+                }
+            }
+            _ => i += 1,
+        }
+    }
+}
+
+fn count_run(bytes: &[u8], from: usize, to: usize, ch: u8) -> usize {
+    bytes[from..to].iter().take_while(|b| **b == ch).count()
+}
+
+fn find_run(bytes: &[u8], from: usize, to: usize, ch: u8, len: usize) -> Option<usize> {
+    let mut i = from;
+    while i < to {
+        if bytes[i] == ch {
+            let run = count_run(bytes, i, to, ch);
+            if run == len {
+                return Some(i);
+            }
+            i += run;
+        } else {
+            i += 1;
+        }
+    }
+
+    None
+}
+
+fn find_display_end(bytes: &[u8], from: usize, to: usize) -> Option<usize> {
+    let mut i = from;
+    while i + 1 < to {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'$' if bytes[i + 1] == b'$' => return Some(i),
+            _ => i += 1,
+        }
+    }
+
+    None
+}
+
+// ? `$ 1$` や `$1 $` は数式にならない
+fn find_inline_end(bytes: &[u8], from: usize, to: usize) -> Option<usize> {
+    if from >= to || bytes[from].is_ascii_whitespace() || bytes[from] == b'$' {
+        return None;
+    }
+
+    let mut i = from;
+    while i < to {
+        match bytes[i] {
+            b'\\' => i += 2,
+            // 段落をまたがない
+            b'\n'
+                if bytes[i + 1..to]
+                    .iter()
+                    .find(|b| !matches!(b, b' ' | b'\t' | b'\r'))
+                    .is_none_or(|b| *b == b'\n') =>
+            {
+                return None;
+            }
+            b'$' if !bytes[i - 1].is_ascii_whitespace() => return Some(i),
+            _ => i += 1,
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segments(markdown: &str) -> Vec<(SegmentKind, &str)> {
+        math_segments(markdown)
+            .into_iter()
+            .map(|segment| {
+                assert_eq!(&markdown[segment.range.clone()], segment.code);
+                (segment.kind, &markdown[segment.range])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn inline_and_display() {
+        assert_eq!(
+            segments("a $x$ b $$\ny\n$$ c"),
+            [(SegmentKind::Inline, "x"), (SegmentKind::Display, "\ny\n")]
+        );
+        // ? 空白で始まる・終わるものは数式にならない
+        assert_eq!(segments("$ x$ $y $"), []);
+        assert_eq!(segments("\\$x$ $y\\$z$"), [(SegmentKind::Inline, "y\\$z")]);
+        // 段落をまたがない
+        assert_eq!(segments("$x\n\ny$"), []);
+        assert_eq!(segments("$x\ny$"), [(SegmentKind::Inline, "x\ny")]);
+    }
+
+    #[test]
+    fn skips_code_and_frontmatter() {
+        assert_eq!(
+            segments("---\na: $x$\n---\n`$y$` $z$"),
+            [(SegmentKind::Inline, "z")]
+        );
+        assert_eq!(segments("``a ` $x$``"), []);
+    }
+
+    #[test]
+    fn codeblocks() {
+        let markdown = "```typ\n$x$\n```\n~~~~\n$y$\n~~~~\n$z$";
+        assert_eq!(
+            segments(markdown),
+            [
+                (SegmentKind::Codeblock("typ".to_string()), "$x$"),
+                (SegmentKind::Inline, "z")
+            ]
+        );
+        // 閉じていないフェンスは末尾まで
+        assert_eq!(
+            segments("````typst\n```\n$x$"),
+            [(SegmentKind::Codeblock("typst".to_string()), "```\n$x$")]
+        );
+    }
+}
@@ -0,0 +1,3 @@
+pub mod lint;
+pub mod markdown;
+pub mod settings;
@@ -0,0 +1,244 @@
+use std::sync::LazyLock;
+
+use serde::Deserialize;
+
+use crate::template::{OffsetMap, Template};
+use crate::vault::markdown::{MathSegment, SegmentKind};
+
+// プラグインの data.json
+// ? 足りない項目は DEFAULT_SETTINGS で補う
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    pub preamble: String,
+    pub processor: ProcessorSettings,
+    pub fail_on_warning: bool,
+    pub disable_package_cache: bool,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct ProcessorSettings {
+    pub inline: Option<ProcessorList>,
+    pub display: Option<ProcessorList>,
+    pub codeblock: Option<ProcessorList>,
+    pub excalidraw: Option<ProcessorList>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct ProcessorList {
+    pub processors: Vec<Processor>,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Processor {
+    pub id: String,
+    pub rendering_engine: String,
+    pub format: String,
+    pub styling: String,
+    #[serde(default)]
+    pub no_preamble: bool,
+}
+
+// 描画するソース
+pub struct Resolved<'a> {
+    pub kind: &'static str,
+    pub processor: &'a Processor,
+    pub code: String,
+    // `code` の位置から数式 (MathSegment.code) の位置へ
+    pub map: OffsetMap,
+}
+
+// ? 既定値は TS の DEFAULT_SETTINGS と同じ JSON から読む
+static DEFAULT_SETTINGS: LazyLock<Settings> = LazyLock::new(|| {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Shared {
+        preamble: String,
+        processor: ProcessorSettings,
+        fail_on_warning: bool,
+        disable_package_cache: bool,
+    }
+
+    let shared: Shared =
+        serde_json::from_str(include_str!("../../../src/data/defaultSettings.json"))
+            .expect("defaultSettings.json should match Settings");
+    Settings {
+        preamble: shared.preamble,
+        processor: shared.processor,
+        fail_on_warning: shared.fail_on_warning,
+        disable_package_cache: shared.disable_package_cache,
+    }
+});
+
+impl Default for Settings {
+    fn default() -> Self {
+        DEFAULT_SETTINGS.clone()
+    }
+}
+
+impl Processor {
+    pub fn is_typst(&self) -> bool {
+        self.rendering_engine == "typst-svg"
+    }
+}
+
+impl Settings {
+    // TypstManager.render と同じ規則でプロセッサーを決める
+    // ? コードブロックは登録された言語だけが対象
+    pub fn resolve(&self, segment: &MathSegment) -> Option<Resolved<'_>> {
+        let mut code = segment.code.clone();
+        let mut map = OffsetMap::default();
+
+        let (kind, processor) = match &segment.kind {
+            SegmentKind::Inline => {
+                // ? プラグイン No more flickering inline math との互換性のため
+                if code.len() >= 4 && code.starts_with("{}") && code.ends_with("{}") {
+                    let start = if code.as_bytes()[2] == b' ' { 3 } else { 2 };
+                    let end = if code.as_bytes()[code.len() - 3] == b' ' {
+                        code.len() - 3
+                    } else {
+                        code.len() - 2
+                    };
+                    code = code.get(start..end).unwrap_or("").to_string();
+                    map.strip_prefix(start);
+                }
+                // ? プラグイン obsidian-equation-citator との互換性のため
+                if code.starts_with("\\ref") {
+                    return None;
+                }
+
+                let processor = find_or_default(&self.processor.inline, &DEFAULTS.inline, |p| {
+                    code.starts_with(&format!("{}:", p.id))
+                });
+                if !processor.id.is_empty() {
+                    code = code[processor.id.len() + 1..].to_string();
+                    map.strip_prefix(processor.id.len() + 1);
+                }
+
+                ("inline", processor)
+            }
+            SegmentKind::Display => {
+                (code, map) = strip_callout(&code);
+
+                let processor = find_or_default(&self.processor.display, &DEFAULTS.display, |p| {
+                    code.starts_with(&p.id)
+                });
+                code = code[processor.id.len()..].to_string();
+                map.strip_prefix(processor.id.len());
+
+                ("display", processor)
+            }
+            SegmentKind::Codeblock(lang) => {
+                let processor = self
+                    .processor
+                    .codeblock
+                    .as_ref()?
+                    .processors
+                    .iter()
+                    .find(|p| &p.id == lang)?;
+
+                ("codeblock", processor)
+            }
+        };
+
+        processor.is_typst().then_some(Resolved {
+            kind,
+            processor,
+            code,
+            map,
+        })
+    }
+
+    pub fn template(&self, processor: &Processor) -> Template {
+        let preamble = (!processor.no_preamble).then_some(self.preamble.as_str());
+        Template::new(preamble, &processor.format)
+    }
+}
+
+struct Defaults {
+    inline: Processor,
+    display: Processor,
+}
+
+// ? DEFAULT_SETTINGS の各リストの最後の要素
+static DEFAULTS: LazyLock<Defaults> = LazyLock::new(|| {
+    let last = |list: &Option<ProcessorList>| {
+        list.as_ref()
+            .and_then(|l| l.processors.last())
+            .cloned()
+            .expect("default processor list should not be empty")
+    };
+
+    Defaults {
+        inline: last(&DEFAULT_SETTINGS.processor.inline),
+        display: last(&DEFAULT_SETTINGS.processor.display),
+    }
+});
+
+fn find_or_default<'a>(
+    list: &'a Option<ProcessorList>,
+    default: &'a Processor,
+    pred: impl Fn(&Processor) -> bool,
+) -> &'a Processor {
+    list.as_ref()
+        .and_then(|l| l.processors.iter().find(|p| pred(p)))
+        .unwrap_or(default)
+}
+
+// 引用・コールアウト内の `> ` を取り除く
+// ? TS の `code.replaceAll(/\n[\s\t]*> /g, '\n')` と同じく，`> ` の前の空行もまとめて取り除く
+fn strip_callout(code: &str) -> (String, OffsetMap) {
+    let mut out = String::with_capacity(code.len());
+    let mut map = OffsetMap::default();
+    let mut rest = code;
+    while let Some(i) = rest.find('\n') {
+        out.push_str(&rest[..=i]);
+        rest = &rest[i + 1..];
+
+        let trimmed = rest.trim_start_matches(is_js_whitespace);
+        if let Some(after) = trimmed.strip_prefix("> ") {
+            map.remove(out.len(), rest.len() - after.len());
+            rest = after;
+        }
+    }
+    out.push_str(rest);
+
+    (out, map)
+}
+
+// JS の正規表現の `\s`
+fn is_js_whitespace(c: char) -> bool {
+    (c.is_whitespace() && c != '\u{85}') || c == '\u{feff}'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strip(code: &str) -> String {
+        strip_callout(code).0
+    }
+
+    #[test]
+    fn callout() {
+        assert_eq!(strip("\n> x +\n  > y\n> "), "\nx +\ny\n");
+        // `>` だけの行は残る
+        assert_eq!(strip("\n> x\n>\n> y"), "\nx\n>\ny");
+    }
+
+    #[test]
+    fn blank_line_in_callout() {
+        let code = "\n> x\n\n> y\n \t\n\t> z";
+        let (stripped, map) = strip_callout(code);
+        assert_eq!(stripped, "\nx\ny\nz");
+        assert_eq!(
+            &code[map.to_original(stripped.find('y').unwrap())..][..1],
+            "y"
+        );
+        assert_eq!(
+            &code[map.to_original(stripped.find('z').unwrap())..][..1],
+            "z"
+        );
+    }
+}
@@ -0,0 +1,752 @@
+use std::collections::hash_map::Entry;
+
+use js_sys::{ArrayBuffer, Uint8Array};
+use rustc_hash::{FxHashMap, FxHashSet};
+use serde_wasm_bindgen::to_value;
+use wasm_bindgen::prelude::*;
+
+use typst::{
+    World,
+    ecow::EcoVec,
+    foundations::{Bytes, Dict},
+    syntax::{LinkedNode, VirtualPath},
+    text::FontInfo,
+};
+
+use crate::analyzer::{self, imports::ImportTarget};
+use crate::compiler::{CompileError, Compiled, Compiler};
+use crate::provider::JsFileProvider;
+use crate::serde::{
+    bracket, diagnostic, font, lint, matrix, needs, package, pdf, processor, snippet, svg, syntax,
+};
+use crate::template::{Filled, Template};
+use crate::utils::{
+    self,
+    char_position::{utf8_to_utf16_offset, utf16_to_utf8_offset},
+};
+use crate::vault::{lint::lint_notes, settings::Settings};
+use crate::{lexer, parser};
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = console)]
+    fn warn(s: &str);
+}
+
+// tar.gz を展開し，typst.toml を検証してからパッケージ内のパスと中身の Map を返す
+// ? fetch の中 (コンパイル中) から呼ばれるので，Typst のメソッドにはしない
+#[wasm_bindgen]
+pub fn unpack_package_archive(spec: &str, targz: &[u8]) -> Result<js_sys::Map, JsValue> {
+    let spec =
+        utils::package::parse_package_spec(spec).map_err(|e| JsValue::from_str(e.as_str()))?;
+    let files =
+        utils::package::unpack_package(&spec, targz).map_err(|e| JsValue::from_str(e.as_str()))?;
+
+    let map = js_sys::Map::new();
+    for (path, data) in files {
+        map.set(
+            &JsValue::from_str(&path),
+            &Uint8Array::from(data.as_slice()),
+        );
+    }
+
+    Ok(map)
+}
+
+#[wasm_bindgen]
+pub struct Typst {
+    compiler: Compiler,
+    // スニペットのトリガー (パターン, 正規表現か) ごとのキャッシュ
+    triggers: FxHashMap<(String, bool), parser::snippet::Trigger>,
+}
+
+#[wasm_bindgen]
+impl Typst {
+    #[wasm_bindgen(constructor)]
+    pub fn new(fetch: js_sys::Function, fontsize: f64) -> Self {
+        #[cfg(debug_assertions)]
+        console_error_panic_hook::set_once();
+
+        Self {
+            compiler: Compiler::new(Box::new(JsFileProvider::new(fetch)), fontsize),
+            triggers: FxHashMap::default(),
+        }
+    }
+
+    pub fn store(
+        &mut self,
+        fonts: Vec<ArrayBuffer>,
+        sources: JsValue,
+        processors: JsValue,
+        preamble: Option<String>,
+    ) -> Result<JsValue, JsValue> {
+        let sources_serde: FxHashMap<String, Vec<u8>> = serde_wasm_bindgen::from_value(sources)
+            .map_err(|e| JsValue::from_str(&format!("failed to deserialize sources: {}", e)))?;
+        let procs_serde: Vec<processor::ProcessorDes> = serde_wasm_bindgen::from_value(processors)
+            .map_err(|e| JsValue::from_str(&format!("failed to deserialize processors: {}", e)))?;
+
+        for f in fonts.iter() {
+            let u8arr = Uint8Array::new(f);
+            let mut vec = vec![0u8; u8arr.length() as usize];
+            u8arr.copy_to(&mut vec);
+
+            self.compiler.world.add_font(Bytes::new(vec));
+        }
+
+        // ソース
+        let mut errors = Vec::new();
+        for (rpath, bytes) in sources_serde {
+            if rpath.starts_with('@') {
+                match utils::package::parse_package_key(&rpath) {
+                    Ok((spec, vpath)) => self.compiler.world.add_package_file(spec, vpath, bytes),
+                    // ? 不正なキーは飛ばして，残りは登録する
+                    Err(message) => errors.push(package::StoreErrorSer {
+                        path: rpath.clone(),
+                        message: message.to_string(),
+                    }),
+                }
+            } else {
+                self.compiler
+                    .world
+                    .add_file_bytes(VirtualPath::new(rpath), bytes);
+            }
+        }
+
+        self.register_processors(procs_serde, &preamble.unwrap_or_default());
+
+        Ok(to_value(&errors)?)
+    }
+
+    // 設定の変更時にプロセッサーと前文を登録し直す
+    pub fn set_processors(&mut self, processors: JsValue, preamble: String) -> Result<(), JsValue> {
+        let procs_serde: Vec<processor::ProcessorDes> = serde_wasm_bindgen::from_value(processors)
+            .map_err(|e| JsValue::from_str(&format!("failed to deserialize processors: {}", e)))?;
+        self.compiler.clear_templates();
+        self.register_processors(procs_serde, &preamble);
+
+        Ok(())
+    }
+
+    fn register_processors(&mut self, processors: Vec<processor::ProcessorDes>, preamble: &str) {
+        for p in processors {
+            let template = Template::new((!p.no_preamble).then_some(preamble), &p.format);
+            self.compiler.world.add_file_text(
+                VirtualPath::new(format!("{}-{}.typ", p.kind, p.id)),
+                template.fill("", false, None).text,
+            );
+            self.compiler.set_template(&p.kind, &p.id, template);
+        }
+    }
+
+    // tar.gz を展開し，typst.toml を検証してから登録する
+    pub fn install_package_archive(
+        &mut self,
+        spec: &str,
+        targz: &[u8],
+    ) -> Result<JsValue, JsValue> {
+        let spec =
+            utils::package::parse_package_spec(spec).map_err(|e| JsValue::from_str(e.as_str()))?;
+        let files = utils::package::unpack_package(&spec, targz)
+            .map_err(|e| JsValue::from_str(e.as_str()))?;
+
+        let mut paths: Vec<String> = files.keys().cloned().collect();
+        paths.sort();
+        self.compiler.world.add_package_files(spec, files);
+
+        Ok(to_value(&paths)?)
+    }
+
+    // `specs` が null なら全てのパッケージを書き出す
+    pub fn export_package_bundle(&self, specs: JsValue) -> Result<Vec<u8>, JsValue> {
+        let specs_serde: Option<Vec<String>> = serde_wasm_bindgen::from_value(specs)
+            .map_err(|e| JsValue::from_str(&format!("failed to deserialize specs: {}", e)))?;
+        let filter = specs_serde
+            .map(|specs| {
+                specs
+                    .iter()
+                    .map(|s| utils::package::parse_package_spec(s))
+                    .collect::<Result<FxHashSet<_>, _>>()
+            })
+            .transpose()
+            .map_err(|e| JsValue::from_str(e.as_str()))?;
+
+        let mut files = self.compiler.world.package_files(filter.as_ref());
+        files.sort_by(|a, b| (a.0.to_string(), &a.1).cmp(&(b.0.to_string(), &b.1)));
+
+        // ? ローカルパッケージと同じ `namespace/name/version/path` の配置にする
+        utils::archive::pack_targz(files.iter().map(|(spec, vpath, bytes)| {
+            (
+                format!(
+                    "{}/{}/{}/{}",
+                    spec.namespace, spec.name, spec.version, vpath
+                ),
+                bytes.as_slice(),
+            )
+        }))
+        .map_err(|e| JsValue::from_str(e.as_str()))
+    }
+
+    pub fn import_package_bundle(&mut self, bundle: &[u8]) -> Result<JsValue, JsValue> {
+        let files =
+            utils::archive::unpack_targz(bundle).map_err(|e| JsValue::from_str(e.as_str()))?;
+
+        let mut errors = Vec::new();
+        let mut grouped: FxHashMap<String, FxHashMap<String, Vec<u8>>> = FxHashMap::default();
+        for (path, bytes) in files {
+            let mut parts = path.splitn(4, '/');
+            let (Some(namespace), Some(name), Some(version), Some(vpath)) =
+                (parts.next(), parts.next(), parts.next(), parts.next())
+            else {
+                errors.push(package::StoreErrorSer {
+                    path,
+                    message: "bundle entry must be under `{namespace}/{name}/{version}/`"
+                        .to_string(),
+                });
+                continue;
+            };
+            grouped
+                .entry(format!("@{}/{}/{}", namespace, name, version))
+                .or_default()
+                .insert(vpath.to_string(), bytes);
+        }
+
+        let mut packages = Vec::new();
+        for (key, files) in grouped {
+            let result = utils::package::parse_package_spec(&key).and_then(|spec| {
+                utils::package::validate_package_files(&spec, &files).map(|_| spec)
+            });
+
+            match result {
+                Ok(spec) => {
+                    packages.push(package::PackageSpecSer::from(&spec));
+                    self.compiler.world.add_package_files(spec, files);
+                }
+                // ? 壊れたパッケージは飛ばして，残りは登録する
+                Err(message) => errors.push(package::StoreErrorSer {
+                    path: key,
+                    message: message.to_string(),
+                }),
+            }
+        }
+
+        Ok(to_value(&package::BundleImportSer { packages, errors })?)
+    }
+
+    pub fn package_dependencies(&self, spec: &str) -> Result<JsValue, JsValue> {
+        let spec =
+            utils::package::parse_package_spec(spec).map_err(|e| JsValue::from_str(e.as_str()))?;
+
+        let graph_ser: Vec<package::PackageDependencySer> = self
+            .compiler
+            .world
+            .package_dependencies(&spec)
+            .iter()
+            .map(|(spec, present, deps)| package::PackageDependencySer {
+                spec: spec.into(),
+                present: *present,
+                dependencies: deps.iter().map(Into::into).collect(),
+            })
+            .collect();
+
+        Ok(to_value(&graph_ser)?)
+    }
+
+    pub fn package_dependents(&self, spec: &str) -> Result<JsValue, JsValue> {
+        let spec =
+            utils::package::parse_package_spec(spec).map_err(|e| JsValue::from_str(e.as_str()))?;
+
+        let dependents_ser: Vec<package::PackageSpecSer> = self
+            .compiler
+            .world
+            .package_dependents(&spec)
+            .iter()
+            .map(Into::into)
+            .collect();
+
+        Ok(to_value(&dependents_ser)?)
+    }
+
+    // 読み込まれていないパッケージなら null を返す
+    pub fn package_manifest(&self, spec: &str) -> Result<JsValue, JsValue> {
+        let spec =
+            utils::package::parse_package_spec(spec).map_err(|e| JsValue::from_str(e.as_str()))?;
+        let Some(manifest) = self.compiler.world.package_manifest(&spec) else {
+            return Ok(JsValue::NULL);
+        };
+        let manifest = manifest.map_err(|e| JsValue::from_str(e.as_str()))?;
+
+        Ok(to_value(&package::PackageManifestSer::from_manifest(
+            &spec, &manifest,
+        ))?)
+    }
+
+    // 読み込み待ちだったファイルを渡す
+    pub fn supply_file(&mut self, path: &str, bytes: Vec<u8>) -> Result<(), JsValue> {
+        let id = utils::package::parse_file_key(path).map_err(|e| JsValue::from_str(e.as_str()))?;
+
+        match id.package() {
+            Some(spec) => self.compiler.world.add_package_file(
+                spec.clone(),
+                &id.vpath().as_rootless_path().to_string_lossy(),
+                bytes,
+            ),
+            None => self
+                .compiler
+                .world
+                .add_file_bytes(id.vpath().clone(), bytes),
+        }
+
+        Ok(())
+    }
+
+    pub fn remove_file(&mut self, path: &str) -> bool {
+        self.compiler.world.remove_file(VirtualPath::new(path))
+    }
+
+    pub fn remove_package(&mut self, spec: &str) -> Result<bool, JsValue> {
+        let spec =
+            utils::package::parse_package_spec(spec).map_err(|e| JsValue::from_str(e.as_str()))?;
+
+        Ok(self.compiler.world.remove_package(&spec))
+    }
+
+    // `@namespace/name/version/path` またはボールト内のパス
+    pub fn invalidate(&mut self, path: &str) -> Result<bool, JsValue> {
+        let id = utils::package::parse_file_key(path).map_err(|e| JsValue::from_str(e.as_str()))?;
+
+        Ok(self.compiler.world.invalidate(id))
+    }
+
+    // 見つからなかったファイルを，invalidate されるまで再取得しないようにする
+    pub fn set_negative_cache(&mut self, enabled: bool) {
+        self.compiler.world.set_negative_cache(enabled);
+    }
+
+    pub fn failed_files(&self) -> JsValue {
+        let mut failed_ser: Vec<package::FailedFileSer> = self
+            .compiler
+            .world
+            .failed_files()
+            .into_iter()
+            .map(|(id, e)| package::FailedFileSer {
+                path: utils::package::file_key(id),
+                message: e.to_string(),
+            })
+            .collect();
+        failed_ser.sort_by(|a, b| a.path.cmp(&b.path));
+
+        to_value(&failed_ser).unwrap()
+    }
+
+    pub fn clear_failures(&mut self) -> usize {
+        self.compiler.world.clear_failures()
+    }
+
+    pub fn latest_packages(&self) -> JsValue {
+        let packages = self.compiler.world.latest_packages();
+        let packages_ser: Vec<package::PackageSpecSer> = packages.iter().map(Into::into).collect();
+
+        to_value(&packages_ser).unwrap()
+    }
+
+    pub fn check_package_imports(&self, code: &str) -> Result<JsValue, JsValue> {
+        let root = typst::syntax::parse(code);
+        let imports = analyzer::imports::package_imports(&LinkedNode::new(&root));
+
+        let imports_ser: Vec<package::PackageImportSer> = imports
+            .iter()
+            .map(|i| {
+                let (spec, latest) = match &i.target {
+                    ImportTarget::Package(spec) => (
+                        Some(spec),
+                        self.compiler.world.latest_package(&spec.versionless()),
+                    ),
+                    ImportTarget::Versionless(versionless) => {
+                        (None, self.compiler.world.latest_package(versionless))
+                    }
+                    ImportTarget::Invalid => (None, None),
+                };
+
+                package::PackageImportSer {
+                    source: i.source.clone(),
+                    from: utf8_to_utf16_offset(code, i.range.start),
+                    to: utf8_to_utf16_offset(code, i.range.end),
+                    spec: spec.map(Into::into),
+                    installed: spec.is_some_and(|s| self.compiler.world.has_package(s)),
+                    latest: latest.map(|l| l.version.to_string()),
+                }
+            })
+            .collect();
+
+        Ok(to_value(&imports_ser)?)
+    }
+
+    // 導入済みの最新バージョンに書き換える
+    pub fn upgrade_package_imports(&self, code: &str) -> String {
+        let root = typst::syntax::parse(code);
+        let imports = analyzer::imports::package_imports(&LinkedNode::new(&root));
+
+        let mut ret = code.to_string();
+        // ? 後ろから置き換えて，範囲がずれないようにする
+        for i in imports.iter().rev() {
+            let (current, latest) = match &i.target {
+                ImportTarget::Package(spec) => (
+                    Some(spec.version),
+                    self.compiler.world.latest_package(&spec.versionless()),
+                ),
+                ImportTarget::Versionless(versionless) => {
+                    (None, self.compiler.world.latest_package(versionless))
+                }
+                ImportTarget::Invalid => continue,
+            };
+
+            if let Some(latest) = latest
+                && current.is_none_or(|v| v < latest.version)
+            {
+                ret.replace_range(i.range.clone(), &format!("\"{}\"", latest));
+            }
+        }
+
+        ret
+    }
+
+    pub fn list_packages(&mut self) -> JsValue {
+        let packages = self.compiler.world.list_packages();
+        let packages_ser: Vec<package::PackageSpecSer> = packages.iter().map(Into::into).collect();
+
+        to_value(&packages_ser).unwrap()
+    }
+
+    pub fn list_fonts(&mut self) -> JsValue {
+        let families = self.compiler.world.book().families();
+        let infos_ser: Vec<font::FontInfoSer> = families
+            .flat_map(|(_, infos)| infos.map(Into::into))
+            .collect();
+
+        to_value(&infos_ser).unwrap()
+    }
+
+    pub fn get_font_info(&self, buffer: JsValue) -> JsValue {
+        let vec = Uint8Array::new(&buffer).to_vec();
+        let bytes = Bytes::new(vec);
+
+        let infos: Vec<font::FontInfoSer> =
+            FontInfo::iter(&bytes).map(|info| (&info).into()).collect();
+
+        to_value(&infos).unwrap()
+    }
+
+    pub fn find_bracket_pairs(&mut self, code: &str) -> JsValue {
+        let pairs = crate::compiler::find_bracket_pairs(code);
+
+        let pairs_ser: Vec<bracket::BracketPairSer> = pairs.iter().map(Into::into).collect();
+
+        to_value(&pairs_ser).unwrap()
+    }
+
+    pub fn enclosing_nodes(
+        &self,
+        code: &str,
+        mode: &str,
+        cursor: usize,
+    ) -> Result<JsValue, JsValue> {
+        let mode = parser::syntax::parse_mode(mode)
+            .ok_or_else(|| JsValue::from_str(&format!("unknown syntax mode: {}", mode)))?;
+        let root = parser::syntax::parse_by_mode(code, mode);
+        let linked = LinkedNode::new(&root);
+
+        let nodes =
+            analyzer::enclosing::enclosing_nodes(&linked, utf16_to_utf8_offset(code, cursor));
+        let nodes_ser: Vec<syntax::SyntaxNodeSer> = nodes
+            .iter()
+            .map(|n| syntax::SyntaxNodeSer::from_node(n, code))
+            .collect();
+
+        Ok(to_value(&nodes_ser)?)
+    }
+
+    pub fn expand_selection(
+        &self,
+        code: &str,
+        mode: &str,
+        from: usize,
+        to: usize,
+    ) -> Result<JsValue, JsValue> {
+        let mode = parser::syntax::parse_mode(mode)
+            .ok_or_else(|| JsValue::from_str(&format!("unknown syntax mode: {}", mode)))?;
+        let root = parser::syntax::parse_by_mode(code, mode);
+        let linked = LinkedNode::new(&root);
+
+        let range = analyzer::selection::expand_selection(
+            &linked,
+            utf16_to_utf8_offset(code, from),
+            utf16_to_utf8_offset(code, to),
+        );
+
+        Ok(to_value(&syntax::SelectionSer::from_range(range, code))?)
+    }
+
+    pub fn shrink_selection(
+        &self,
+        code: &str,
+        mode: &str,
+        from: usize,
+        to: usize,
+    ) -> Result<JsValue, JsValue> {
+        let mode = parser::syntax::parse_mode(mode)
+            .ok_or_else(|| JsValue::from_str(&format!("unknown syntax mode: {}", mode)))?;
+        let root = parser::syntax::parse_by_mode(code, mode);
+        let linked = LinkedNode::new(&root);
+
+        let range = analyzer::selection::shrink_selection(
+            &linked,
+            utf16_to_utf8_offset(code, from),
+            utf16_to_utf8_offset(code, to),
+        );
+
+        Ok(to_value(&syntax::SelectionSer::from_range(range, code))?)
+    }
+
+    pub fn edit_table(
+        &self,
+        code: &str,
+        mode: &str,
+        cursor: usize,
+        op: &str,
+    ) -> Result<JsValue, JsValue> {
+        let mode = parser::syntax::parse_mode(mode)
+            .ok_or_else(|| JsValue::from_str(&format!("unknown syntax mode: {}", mode)))?;
+        let op = analyzer::matrix::TableOp::parse(op)
+            .ok_or_else(|| JsValue::from_str(&format!("unknown table operation: {}", op)))?;
+        let root = parser::syntax::parse_by_mode(code, mode);
+        let linked = LinkedNode::new(&root);
+
+        let edit =
+            analyzer::matrix::edit_table(&linked, code, utf16_to_utf8_offset(code, cursor), op)
+                .ok_or_else(|| JsValue::from_str("no mat or cases at cursor"))?;
+
+        Ok(to_value(&matrix::TableEditSer::from(&edit))?)
+    }
+
+    pub fn syntax_context(&self, code: &str, mode: &str, cursor: usize) -> Result<String, JsValue> {
+        let mode = parser::syntax::parse_mode(mode)
+            .ok_or_else(|| JsValue::from_str(&format!("unknown syntax mode: {}", mode)))?;
+        let root = parser::syntax::parse_by_mode(code, mode);
+        let linked = LinkedNode::new(&root);
+
+        let context =
+            analyzer::context::syntax_context(&linked, mode, utf16_to_utf8_offset(code, cursor));
+
+        Ok(context.as_str().into())
+    }
+
+    pub fn can_fire_snippet(
+        &self,
+        code: &str,
+        mode: &str,
+        cursor: usize,
+        filter: &str,
+    ) -> Result<bool, JsValue> {
+        let mode = parser::syntax::parse_mode(mode)
+            .ok_or_else(|| JsValue::from_str(&format!("unknown syntax mode: {}", mode)))?;
+        let root = parser::syntax::parse_by_mode(code, mode);
+        let linked = LinkedNode::new(&root);
+
+        let context =
+            analyzer::context::syntax_context(&linked, mode, utf16_to_utf8_offset(code, cursor));
+
+        analyzer::context::match_context(context, filter).map_err(|e| JsValue::from_str(&e))
+    }
+
+    // トリガーが一致しなければ null を返す
+    pub fn expand_snippet(
+        &mut self,
+        body: &str,
+        trigger: &str,
+        regex: bool,
+        before: &str,
+    ) -> Result<JsValue, JsValue> {
+        let key = (trigger.to_string(), regex);
+        let trigger = match self.triggers.entry(key) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(
+                parser::snippet::Trigger::new(trigger, regex)
+                    .map_err(|e| JsValue::from_str(&format!("invalid trigger: {}", e)))?,
+            ),
+        };
+        let Some(matched) = trigger.matches(before) else {
+            return Ok(JsValue::NULL);
+        };
+
+        let tokens = parser::snippet::substitute_captures(
+            lexer::snippet::snippet_lexer(body),
+            &matched.captures,
+        );
+        let expansion = parser::snippet::expand(&tokens);
+
+        Ok(to_value(&snippet::ExpansionSer::from_expansion(
+            &expansion,
+            utf8_to_utf16_offset(before, matched.start),
+        ))?)
+    }
+
+    // ? ちらつき防止のためカーソルの親括弧の計算は TS 側でする
+    pub fn mitex(&mut self, code: &str) -> Result<JsValue, JsValue> {
+        match crate::compiler::mitex(code) {
+            Ok(result) => Ok(JsValue::from_str(&result)),
+            Err(error) => Err(JsValue::from_str(&error)),
+        }
+    }
+
+    fn compile_error(
+        &self,
+        error: CompileError,
+        filled: Option<&Filled>,
+    ) -> Result<JsValue, JsValue> {
+        match error {
+            CompileError::NeedsFiles(ids) => needs::needs_files(ids),
+            CompileError::NoPages => Err(JsValue::from_str("document has no pages")),
+            CompileError::Diagnostics(errs) => {
+                let diags: Vec<diagnostic::SourceDiagnosticSer> = errs
+                    .iter()
+                    .map(|d| match filled {
+                        Some(filled) => diagnostic::SourceDiagnosticSer::from_filled_diag(
+                            d,
+                            &self.compiler.world,
+                            filled,
+                        ),
+                        None => diagnostic::SourceDiagnosticSer::from_diag(d, &self.compiler.world),
+                    })
+                    .collect();
+                Err(to_value(&diags).unwrap())
+            }
+        }
+    }
+
+    // `inputs` は `sys.inputs` (undefined なら空)
+    // ? 変換できない値のために描画を失敗させず，警告を出して空として扱う
+    fn set_inputs(&mut self, inputs: JsValue) {
+        let inputs = serde_wasm_bindgen::from_value::<Option<Dict>>(inputs).unwrap_or_else(|e| {
+            warn(&format!("failed to deserialize inputs: {}", e));
+            None
+        });
+        self.compiler.set_inputs(inputs.unwrap_or_default());
+    }
+
+    pub fn svg(
+        &mut self,
+        code: &str,
+        kind: &str,
+        id: &str,
+        inputs: JsValue,
+    ) -> Result<JsValue, JsValue> {
+        self.set_inputs(inputs);
+        match self.compiler.svg(code, kind, id) {
+            Ok(Compiled { output, warnings }) => {
+                svg::svg(output, warnings, &self.compiler.world, None)
+            }
+            Err(e) => self.compile_error(e, None),
+        }
+    }
+
+    // store で登録したプロセッサーで数式を包んで描画する
+    // 診断には数式・前文中の位置 (origin) が付く
+    pub fn svg_snippet(
+        &mut self,
+        code: &str,
+        kind: &str,
+        id: &str,
+        width: Option<f64>,
+        inputs: JsValue,
+    ) -> Result<JsValue, JsValue> {
+        self.set_inputs(inputs);
+        let filled = self
+            .compiler
+            .template(kind, id)
+            .ok_or_else(|| JsValue::from_str(&format!("unknown processor: {}/{}", kind, id)))?
+            .fill(code, kind == "display", width);
+
+        match self.compiler.svg(&filled.text, kind, id) {
+            Ok(Compiled { output, warnings }) => {
+                svg::svg(output, warnings, &self.compiler.world, Some(&filled))
+            }
+            Err(e) => self.compile_error(e, Some(&filled)),
+        }
+    }
+
+    // svg_snippet と同じ数式の診断を Typst CLI と同じ形式で書き出す
+    // ? 描画したときの World は他の数式のコンパイルで置き換わっているので，診断を表示するときにコンパイルし直す
+    pub fn report_snippet(
+        &mut self,
+        code: &str,
+        kind: &str,
+        id: &str,
+        inputs: JsValue,
+    ) -> Result<JsValue, JsValue> {
+        self.set_inputs(inputs);
+        let filled = self
+            .compiler
+            .template(kind, id)
+            .ok_or_else(|| JsValue::from_str(&format!("unknown processor: {}/{}", kind, id)))?
+            .fill(code, kind == "display", None);
+
+        let diags = match self.compiler.check(&filled.text, kind, id) {
+            Ok(Compiled { warnings, .. }) => warnings,
+            Err(CompileError::Diagnostics(errs)) => errs,
+            Err(CompileError::NeedsFiles(ids)) => return needs::needs_files(ids),
+            Err(CompileError::NoPages) => EcoVec::new(),
+        };
+        let reports: Vec<diagnostic::ReportSer> = diags
+            .iter()
+            .map(|d| diagnostic::ReportSer::new(d, &self.compiler.world))
+            .collect();
+
+        Ok(to_value(&reports)?)
+    }
+
+    // 警告はファイルごとにまとめても返す。options.severity で重大度を絞り，options.inputs を `sys.inputs` にする
+    pub fn pdf(
+        &mut self,
+        filename: &str,
+        code: &str,
+        options: JsValue,
+    ) -> Result<JsValue, JsValue> {
+        let options: Option<pdf::PdfOptionsDes> = serde_wasm_bindgen::from_value(options)
+            .map_err(|e| JsValue::from_str(&format!("failed to deserialize options: {}", e)))?;
+        let options = options.unwrap_or_default();
+        let severity = options.severity()?;
+        self.compiler.set_inputs(options.inputs);
+
+        match self.compiler.pdf(filename, code) {
+            Ok(Compiled { output, warnings }) => {
+                pdf::pdf(output, warnings, &self.compiler.world, severity)
+            }
+            Err(e) => self.compile_error(e, None),
+        }
+    }
+
+    // 全てのノートの数式をコンパイルし，ノートごとのエラーと警告を返す
+    pub fn lint_notes(&mut self, notes: JsValue, settings: JsValue) -> Result<JsValue, JsValue> {
+        let notes: Vec<lint::NoteDes> = serde_wasm_bindgen::from_value(notes)
+            .map_err(|e| JsValue::from_str(&format!("failed to deserialize notes: {}", e)))?;
+        let settings: Settings = serde_wasm_bindgen::from_value(settings)
+            .map_err(|e| JsValue::from_str(&format!("failed to deserialize settings: {}", e)))?;
+        // ? ノートごとの inputs は渡せないので空にする
+        self.compiler.set_inputs(Dict::new());
+
+        let reports = match lint_notes(
+            &mut self.compiler,
+            &settings,
+            notes.iter().map(|n| (n.path.as_str(), n.markdown.as_str())),
+            |compiler, markdown, d| lint::LintDiagnosticSer::new(&d, markdown, compiler.world()),
+        ) {
+            Ok(reports) => reports,
+            Err(e) => return self.compile_error(e, None),
+        };
+
+        let reports_ser: Vec<lint::NoteReportSer> =
+            reports.into_iter().map(lint::NoteReportSer::from).collect();
+
+        Ok(to_value(&reports_ser)?)
+    }
+}
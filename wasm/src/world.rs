@@ -1,38 +1,54 @@
-use std::{path::PathBuf, str::FromStr, sync::Mutex};
+use std::{str::FromStr, sync::Mutex};
 
 use chrono::{DateTime, Datelike, FixedOffset, Local, Utc};
 use rustc_hash::{FxHashMap, FxHashSet};
-use send_wrapper::SendWrapper;
-use wasm_bindgen::{JsCast, JsValue};
 
 use typst::{
     Library, LibraryExt, World,
     diag::{FileError, FileResult, PackageError},
-    foundations::{Bytes, Content, Datetime, Property, Style, Value},
+    ecow::EcoString,
+    foundations::{Bytes, Content, Datetime, Dict, Property, Style, Value},
     layout::Abs,
-    syntax::{FileId, Source, VirtualPath, package::PackageSpec},
+    syntax::{
+        FileId, LinkedNode, Source, VirtualPath,
+        package::{PackageManifest, PackageSpec, VersionlessPackageSpec},
+        parse,
+    },
     text::{Font, FontBook, TextElem},
     utils::LazyHash,
     visualize::{Color, Paint},
 };
 use typst_ide::IdeWorld;
 
+use crate::analyzer::imports::{ImportTarget, package_imports};
+use crate::provider::FileProvider;
+use crate::utils::package::{file_key, validate_manifest};
 use crate::vfs::FileSlot;
 
+// ノートごとに inputs が変わるので，開いているノートの数くらいは取っておく
+const MAX_CACHED_LIBRARIES: usize = 8;
+
 pub struct WasmWorld {
     main: FileId,
     library: LazyHash<Library>,
+    fontsize: f64,
+    // `sys.inputs`
+    inputs: Dict,
+    // 以前の inputs で作ったライブラリ (古い順)
+    libraries: Vec<(Dict, LazyHash<Library>)>,
     book: LazyHash<FontBook>,
     fonts: Vec<Font>,
     slots: Mutex<FxHashMap<FileId, FileSlot>>,
     now: DateTime<Utc>,
 
-    read: SendWrapper<js_sys::Function>,
+    provider: Box<dyn FileProvider>,
     packages: FxHashSet<PackageSpec>,
+    negative_cache: bool,
+    pending: Mutex<FxHashSet<FileId>>,
 }
 
 impl WasmWorld {
-    pub fn new(read: js_sys::Function, fontsize: f64) -> Self {
+    pub fn new(provider: Box<dyn FileProvider>, fontsize: f64) -> Self {
         // ファイルシステムを設定
         let main = FileId::new(None, VirtualPath::new("main.typ"));
         let mut slots = FxHashMap::default();
@@ -48,30 +64,40 @@ impl WasmWorld {
             }
         }
 
-        // ライブラリを設定
-        let mut library = Library::default();
-        // 値 `fontsize` を定義
-        let fontsize_abs = Abs::pt(fontsize / 1.25);
-        let fontsize_val = Value::Length(fontsize_abs.into());
-        // 値 `CURSOR` を定義
-        let cursor_elem = TextElem::new("▮".into());
-        let cursor_paint = Paint::Solid(Color::from_str("#44f").unwrap());
-        let cursor_style = Style::Property(Property::new(TextElem::fill, cursor_paint));
-        let cursor_val = Value::Content(Content::new(cursor_elem).styled(cursor_style));
-        // グローバル定義に追加
-        library.global.scope_mut().define("fontsize", fontsize_val);
-        library.global.scope_mut().define("CURSOR", cursor_val);
-
         Self {
             main,
-            library: LazyHash::new(library),
+            library: LazyHash::new(build_library(fontsize, Dict::new())),
+            fontsize,
+            inputs: Dict::new(),
+            libraries: Vec::new(),
             book,
             fonts,
             slots: Mutex::new(slots),
             now: Utc::now(),
 
-            read: SendWrapper::new(read),
+            provider,
             packages: FxHashSet::default(),
+            negative_cache: false,
+            pending: Mutex::new(FxHashSet::default()),
+        }
+    }
+
+    // `sys.inputs` を差し替える
+    // ? ライブラリを作り直すとキャッシュが効かなくなるので，最近使ったものは inputs ごとに取っておく
+    pub fn set_inputs(&mut self, inputs: Dict) {
+        if self.inputs == inputs {
+            return;
+        }
+
+        let library = match self.libraries.iter().position(|(i, _)| *i == inputs) {
+            Some(index) => self.libraries.remove(index).1,
+            None => LazyHash::new(build_library(self.fontsize, inputs.clone())),
+        };
+        let old_inputs = std::mem::replace(&mut self.inputs, inputs);
+        let old_library = std::mem::replace(&mut self.library, library);
+        self.libraries.push((old_inputs, old_library));
+        if self.libraries.len() > MAX_CACHED_LIBRARIES {
+            self.libraries.remove(0);
         }
     }
 
@@ -83,7 +109,13 @@ impl WasmWorld {
     pub fn replace(&mut self, new: &str) {
         let mut m = self.slots.lock().unwrap();
 
-        m.get_mut(&self.main).unwrap().replace(new);
+        match m.get_mut(&self.main) {
+            Some(slot) => slot.replace(new),
+            // ? remove_file などで消されていた場合
+            None => {
+                m.insert(self.main, FileSlot::new_from_text(self.main, new.into()));
+            }
+        }
     }
 
     pub fn add_file_text(&self, vpath: VirtualPath, text: String) {
@@ -108,51 +140,217 @@ impl WasmWorld {
         self.packages.insert(spec);
     }
 
+    pub fn add_package_files(&mut self, spec: PackageSpec, files: FxHashMap<String, Vec<u8>>) {
+        let mut m = self.slots.lock().unwrap();
+        for (vpath, bytes) in files {
+            let file_id = FileId::new(Some(spec.clone()), VirtualPath::new(vpath));
+            m.insert(file_id, FileSlot::new_from_bytes(file_id, bytes));
+        }
+
+        self.packages.insert(spec);
+    }
+
+    // 読み込み済みのパッケージのファイル (`filter` が None なら全て)
+    pub fn package_files(
+        &self,
+        filter: Option<&FxHashSet<PackageSpec>>,
+    ) -> Vec<(PackageSpec, String, Bytes)> {
+        let m = self.slots.lock().unwrap();
+
+        m.iter()
+            .filter_map(|(id, slot)| {
+                let spec = id.package()?;
+                if filter.is_some_and(|f| !f.contains(spec)) {
+                    return None;
+                }
+                let bytes = slot.bytes().ok()?;
+                let vpath = id.vpath().as_rootless_path().to_str()?.to_string();
+
+                Some((spec.clone(), vpath, bytes))
+            })
+            .collect()
+    }
+
+    pub fn remove_file(&self, vpath: VirtualPath) -> bool {
+        let mut m = self.slots.lock().unwrap();
+
+        m.remove(&FileId::new(None, vpath)).is_some()
+    }
+
+    pub fn remove_package(&mut self, spec: &PackageSpec) -> bool {
+        let mut m = self.slots.lock().unwrap();
+        m.retain(|id, _| id.package() != Some(spec));
+
+        self.packages.remove(spec)
+    }
+
+    // ? 次に読まれたときに fetch し直させる
+    pub fn invalidate(&self, id: FileId) -> bool {
+        let mut m = self.slots.lock().unwrap();
+
+        m.remove(&id).is_some()
+    }
+
+    // コンパイル中に読み込み待ちになったファイルを取り出す
+    pub fn take_pending(&self) -> Vec<FileId> {
+        self.pending.lock().unwrap().drain().collect()
+    }
+
+    pub fn set_negative_cache(&mut self, enabled: bool) {
+        self.negative_cache = enabled;
+    }
+
+    // 読み込みに失敗したファイルとその理由
+    pub fn failed_files(&self) -> Vec<(FileId, FileError)> {
+        let m = self.slots.lock().unwrap();
+
+        m.iter()
+            .filter_map(|(id, slot)| slot.bytes().err().map(|e| (*id, e)))
+            .collect()
+    }
+
+    // 失敗したファイルを全て捨て，次のコンパイルで再取得させる
+    pub fn clear_failures(&self) -> usize {
+        let mut m = self.slots.lock().unwrap();
+        let before = m.len();
+        m.retain(|_, slot| slot.bytes().is_ok());
+
+        before - m.len()
+    }
+
     pub fn list_packages(&self) -> Vec<PackageSpec> {
         self.packages.iter().cloned().collect()
     }
 
-    pub fn add_font(&mut self, data: Bytes) {
-        for f in Font::iter(data) {
-            self.book.push(f.info().clone());
-            self.fonts.push(f);
+    // 名前ごとに，導入済みの最新バージョンを返す
+    pub fn latest_packages(&self) -> Vec<PackageSpec> {
+        let mut latest: FxHashMap<VersionlessPackageSpec, PackageSpec> = FxHashMap::default();
+        for spec in &self.packages {
+            latest
+                .entry(spec.versionless())
+                .and_modify(|l| {
+                    if spec.version > l.version {
+                        *l = spec.clone();
+                    }
+                })
+                .or_insert_with(|| spec.clone());
         }
+
+        latest.into_values().collect()
     }
 
-    fn fetch(&self, rpath: String) -> Result<JsValue, JsValue> {
-        return self.read.call1(&JsValue::NULL, &rpath.into());
+    pub fn latest_package(&self, versionless: &VersionlessPackageSpec) -> Option<PackageSpec> {
+        self.packages
+            .iter()
+            .filter(|spec| spec.versionless() == *versionless)
+            .max_by_key(|spec| spec.version)
+            .cloned()
     }
 
-    fn fetch_file(&self, rpath: String, spec: Option<&PackageSpec>) -> FileResult<Bytes> {
-        let f = |e: JsValue| {
-            if let Some(value) = e.as_f64() {
-                return match value as i64 {
-                    0 => FileError::Other(Some("implementation constraints".into())),
+    pub fn has_package(&self, spec: &PackageSpec) -> bool {
+        self.packages.contains(spec)
+    }
 
-                    10 => FileError::AccessDenied,
-                    11 => FileError::IsDirectory,
-                    12 => FileError::NotFound(PathBuf::from(rpath.clone())),
+    // パッケージのソースが直接読み込むパッケージ
+    fn direct_dependencies(&self, spec: &PackageSpec) -> Vec<PackageSpec> {
+        let filter = FxHashSet::from_iter([spec.clone()]);
+        let mut deps: Vec<PackageSpec> = self
+            .package_files(Some(&filter))
+            .into_iter()
+            .filter(|(_, vpath, _)| vpath.ends_with(".typ"))
+            .filter_map(|(_, _, bytes)| std::str::from_utf8(&bytes).ok().map(parse))
+            .flat_map(|root| package_imports(&LinkedNode::new(&root)))
+            .filter_map(|i| match i.target {
+                ImportTarget::Package(dep) if dep != *spec => Some(dep),
+                _ => None,
+            })
+            .collect();
+        deps.sort_by_cached_key(ToString::to_string);
+        deps.dedup();
+
+        deps
+    }
 
-                    20 => FileError::Package(PackageError::MalformedArchive(Some(
-                        "invalid package format".into(),
-                    ))),
-                    21 => FileError::Package(PackageError::NetworkFailed(Some(
-                        "network connection error".into(),
-                    ))),
-                    22 => FileError::Package(PackageError::NotFound(spec.unwrap().clone())),
-                    _ => FileError::Other(Some("unexpected error".into())),
-                };
+    // 推移的な依存関係を，(パッケージ, VFS にあるか, 直接の依存) の組で返す
+    pub fn package_dependencies(
+        &self,
+        root: &PackageSpec,
+    ) -> Vec<(PackageSpec, bool, Vec<PackageSpec>)> {
+        let mut ret = Vec::new();
+        let mut visited = FxHashSet::default();
+        let mut queue = std::collections::VecDeque::from([root.clone()]);
+
+        while let Some(spec) = queue.pop_front() {
+            if !visited.insert(spec.clone()) {
+                continue;
             }
-            FileError::Other(e.as_string().map(Into::into))
-        };
 
-        self.fetch(rpath.clone()).map_err(f).and_then(|js_value| {
-            if let Some(u8arr) = js_value.dyn_ref::<js_sys::Uint8Array>() {
-                Ok(Bytes::new(u8arr.to_vec()))
+            let present = self.has_package(&spec);
+            // ? VFS にないパッケージは取得せず，そこで辿るのをやめる
+            let deps = if present {
+                self.direct_dependencies(&spec)
             } else {
-                Err(FileError::Other(Some("unexpected error".into())))
-            }
-        })
+                Vec::new()
+            };
+            queue.extend(deps.iter().cloned());
+            ret.push((spec, present, deps));
+        }
+
+        ret
+    }
+
+    // `spec` を推移的に必要とする，導入済みのパッケージ
+    pub fn package_dependents(&self, spec: &PackageSpec) -> Vec<PackageSpec> {
+        let mut ret: Vec<PackageSpec> = self
+            .packages
+            .iter()
+            .filter(|p| *p != spec)
+            .filter(|p| {
+                self.package_dependencies(p)
+                    .iter()
+                    .any(|(dep, _, _)| dep == spec)
+            })
+            .cloned()
+            .collect();
+        ret.sort_by_cached_key(ToString::to_string);
+
+        ret
+    }
+
+    // ? プロバイダーからは取得せず，読み込み済みのファイルだけを見る．typst.toml がなければ None
+    pub fn package_manifest(
+        &self,
+        spec: &PackageSpec,
+    ) -> Option<Result<PackageManifest, EcoString>> {
+        let m = self.slots.lock().unwrap();
+        let file = |vpath: &str| {
+            m.get(&FileId::new(Some(spec.clone()), VirtualPath::new(vpath)))
+                .and_then(|slot| slot.bytes().ok())
+        };
+
+        let manifest = file("typst.toml")?;
+        Some(validate_manifest(spec, &manifest, |p| file(p).is_some()))
+    }
+
+    pub fn add_font(&mut self, data: Bytes) {
+        for f in Font::iter(data) {
+            self.book.push(f.info().clone());
+            self.fonts.push(f);
+        }
+    }
+
+    fn should_refetch(&self, slot: &FileSlot) -> bool {
+        match slot.bytes() {
+            Ok(_) => false,
+            // ? 存在しないことが確定しているものは，invalidate されるまで再取得しない
+            Err(
+                FileError::NotFound(_)
+                | FileError::AccessDenied
+                | FileError::IsDirectory
+                | FileError::Package(PackageError::NotFound(_) | PackageError::VersionNotFound(..)),
+            ) => !self.negative_cache,
+            Err(_) => true,
+        }
     }
 
     fn read<F, T>(&self, id: FileId, f: F) -> FileResult<T>
@@ -161,25 +359,22 @@ impl WasmWorld {
     {
         let mut m = self.slots.lock().unwrap();
 
-        if m.get(&id).map_or(true, |slot| slot.bytes().is_err()) {
-            let result = match id.package() {
-                Some(spec) => self.fetch_file(
-                    format!(
-                        "@{}/{}/{}/{}",
-                        spec.namespace,
-                        spec.name,
-                        spec.version,
-                        id.vpath().as_rootless_path().to_str().unwrap()
-                    ),
-                    Some(&spec),
-                ),
-                None => self.fetch_file(
-                    id.vpath().as_rootless_path().to_str().unwrap().to_string(),
-                    None,
-                ),
-            };
-
-            m.insert(id, FileSlot::new_from_result(id, result));
+        if m.get(&id).is_none_or(|slot| self.should_refetch(slot)) {
+            match self.provider.fetch(&file_key(id), id.package()) {
+                Some(result) => {
+                    m.insert(id, FileSlot::new_from_result(id, result));
+                }
+                None => {
+                    self.pending.lock().unwrap().insert(id);
+
+                    // ? スロットには入れず，ホストが用意した後のコンパイルで再取得する
+                    let mut slot = FileSlot::new_from_result(
+                        id,
+                        Err(FileError::Other(Some("file is being loaded".into()))),
+                    );
+                    return f(&mut slot);
+                }
+            }
         }
 
         f(m.get_mut(&id).unwrap())
@@ -249,3 +444,21 @@ impl IdeWorld for WasmWorld {
         std::vec![]
     }
 }
+
+// `fontsize` と `CURSOR` を追加したライブラリ
+fn build_library(fontsize: f64, inputs: Dict) -> Library {
+    let mut library = Library::builder().with_inputs(inputs).build();
+    // 値 `fontsize` を定義
+    let fontsize_abs = Abs::pt(fontsize / 1.25);
+    let fontsize_val = Value::Length(fontsize_abs.into());
+    // 値 `CURSOR` を定義
+    let cursor_elem = TextElem::new("▮".into());
+    let cursor_paint = Paint::Solid(Color::from_str("#44f").unwrap());
+    let cursor_style = Style::Property(Property::new(TextElem::fill, cursor_paint));
+    let cursor_val = Value::Content(Content::new(cursor_elem).styled(cursor_style));
+    // グローバル定義に追加
+    library.global.scope_mut().define("fontsize", fontsize_val);
+    library.global.scope_mut().define("CURSOR", cursor_val);
+
+    library
+}